@@ -1,51 +1,110 @@
+use crate::bed::{bed, bed_simple, is_bed};
 use crate::features::Feature;
-use crate::lib::{Config, ConfigFeature, Database, Region};
+use crate::lib::{ConfigData, ConfigFeature, Database, Region};
 use libbigwig::*;
-use rocks::rocksdb::*;
 use std::collections::HashMap;
 use std::ffi::{CStr, CString};
 use std::mem;
-use std::mem::*;
 use std::path::Path;
 use std::sync::Arc;
 
-pub fn node_id_to_region(database: Arc<Database>, node_id: u64) -> Option<Region> {
-    let raw_bytes: [u8; 8] = unsafe { transmute(node_id) };
-    let db_name = &database.rocks;
-    let db = DB::open(&Options::default(), db_name).unwrap();
-    let coord_option = db.get(&ReadOptions::default(), &raw_bytes);
-    if let Ok(ref coord_raw) = coord_option {
-        let coord_result = Region::new(String::from_utf8(coord_raw.to_vec()).unwrap());
-        if let Ok(coord) = coord_result {
-            return Some(coord);
+// The max number of bins of a bigWig track, which is also the default for long regions.
+pub const MAX_BINS: u32 = 1000;
+// Regions up to this length are answered with raw intervals unless `bins` is given.
+pub const MAX_RAW_INTERVAL: u64 = 10000;
+
+// The number of bins for a region, where each bin spans at least 1 bp.
+pub fn bin_count(bins: Option<u32>, interval: u64) -> Option<u32> {
+    let max = (MAX_BINS as u64).min(interval.max(1)) as u32;
+    match bins {
+        Some(bins) => Some(bins.max(1).min(max)),
+        None if interval > MAX_RAW_INTERVAL => Some(max),
+        None => None,
+    }
+}
+
+// The `i`th of `bins` bins which split the region evenly, as bwStats does.
+fn bin_bounds(coord: &Region, bins: u32, i: u32) -> (u64, u64) {
+    let interval = coord.stop.saturating_sub(coord.start);
+    (
+        coord.start + interval * i as u64 / bins as u64,
+        coord.start + interval * (i as u64 + 1) / bins as u64,
+    )
+}
+
+// The statistic of each bin of a bigWig track, as `enum bwStatsType` of libBigWig.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BigWigStat {
+    Mean,
+    Std,
+    Max,
+    Min,
+    Cov, // The fraction of bases covered.
+    Sum,
+}
+
+impl BigWigStat {
+    pub fn new(stat: Option<&str>) -> Result<BigWigStat, String> {
+        match stat {
+            None | Some("mean") => Ok(BigWigStat::Mean),
+            Some("std") => Ok(BigWigStat::Std),
+            Some("max") => Ok(BigWigStat::Max),
+            Some("min") => Ok(BigWigStat::Min),
+            Some("cov") => Ok(BigWigStat::Cov),
+            Some("sum") => Ok(BigWigStat::Sum),
+            Some(stat) => Err(format!("Unknown stat: {}", stat)),
+        }
+    }
+
+    fn code(&self) -> i32 {
+        match *self {
+            BigWigStat::Mean => 0,
+            BigWigStat::Std => 1,
+            BigWigStat::Max => 2,
+            BigWigStat::Min => 3,
+            BigWigStat::Cov => 4,
+            BigWigStat::Sum => 5,
         }
     }
-    return None;
+}
+
+pub fn node_id_to_region(
+    data: &ConfigData,
+    database: Arc<Database>,
+    node_id: u64,
+) -> Option<Region> {
+    database.rocks.get(&data.name)?.get(node_id)
+}
+
+pub fn node_ids_to_regions(
+    data: &ConfigData,
+    database: &Database,
+    node_ids: &[u64],
+) -> HashMap<u64, Region> {
+    match database.rocks.get(&data.name) {
+        Some(index) => index.multi_get(node_ids),
+        None => HashMap::new(),
+    }
 }
 
 // Ideally, nodes belonging to the same path should be queried only once at a time, and then the feature should be divided for each node.
 // Should caching on KVS
 pub fn node_id_to_feature(
-    config: Arc<Config>,
+    data: &ConfigData,
     database: Arc<Database>,
     node_id: u64,
 ) -> Vec<Vec<Feature>> {
-    let raw_bytes: [u8; 8] = unsafe { transmute(node_id) };
-    let db_name = &database.rocks;
-    let db = DB::open(&Options::default(), db_name).unwrap();
-    let coord_option = db.get(&ReadOptions::default(), &raw_bytes);
     let mut vec: Vec<Vec<Feature>> = vec![];
-    if let Ok(ref coord_raw) = coord_option {
-        let coord_result = Region::new(String::from_utf8(coord_raw.to_vec()).unwrap());
-        if let Ok(mut coord) = coord_result {
+    if let Some(index) = database.rocks.get(&data.name) {
+        if let Some(mut coord) = index.get(node_id) {
             coord.start_minus();
-            for feature in config.data[0].features.iter() {
+            for feature in data.features.iter() {
                 let path = Path::new(&feature.url);
                 let chr_prefix = feature.chr_prefix.clone().unwrap_or("".to_string());
                 // println!("Parsing:  {:?}", path);
-                match path.extension().unwrap().to_str() {
+                match feature_format(&feature.url) {
                     Some("bed") => {
-                        vec.push(giggle(feature, &coord, chr_prefix));
+                        vec.push(bed(feature, &coord, chr_prefix, &database.beds));
                     }
                     Some("bb") => vec.push(libbigbed(feature, &coord, chr_prefix)),
                     Some("bw") => vec.push(libbigwig(feature, &coord, chr_prefix)),
@@ -57,50 +116,65 @@ pub fn node_id_to_feature(
     return vec;
 }
 
+// Bgzipped BED files are treated as BED, otherwise the extension decides the format.
+fn feature_format(url: &String) -> Option<&str> {
+    if is_bed(url) {
+        return Some("bed");
+    }
+    Path::new(url).extension().and_then(|t| t.to_str())
+}
+
 pub fn regions_to_feature(
-    config: &Arc<Config>,
+    data: &ConfigData,
+    database: &Database,
     track_type: &String,
     coord: Vec<Region>,
     bins: Option<u32>,
+    stat: BigWigStat,
 ) -> Vec<Vec<Vec<Feature>>> {
     coord
         .into_iter()
-        .map(|a| region_to_feature(config, track_type, a, bins))
+        .map(|a| region_to_feature(data, database, track_type, a, bins, stat))
         .collect()
 }
 
 pub fn regions_to_feature_map(
-    config: &Arc<Config>,
+    data: &ConfigData,
+    database: &Database,
     track_type: &String,
     coord: Vec<Region>,
     bins: Option<u32>,
+    stat: BigWigStat,
 ) -> Vec<HashMap<String, Vec<Feature>>> {
     coord
         .into_iter()
-        .map(|a| region_to_feature_map(config, track_type, a, bins))
+        .map(|a| region_to_feature_map(data, database, track_type, a, bins, stat))
         .collect()
 }
 
 pub fn region_to_feature(
-    config: &Arc<Config>,
+    data: &ConfigData,
+    database: &Database,
     track_type: &String,
     coord: Region,
     bins: Option<u32>,
+    stat: BigWigStat,
 ) -> Vec<Vec<Feature>> {
+    let bins = bin_count(bins, coord.stop.saturating_sub(coord.start));
     let mut vec: Vec<Vec<Feature>> = vec![];
-    for feature in config.data[0].features.iter() {
+    for feature in data.features.iter() {
         let path = Path::new(&feature.url);
         let chr_prefix = feature.chr_prefix.clone().unwrap_or("".to_string());
-        match path.extension().unwrap().to_str() {
+        match feature_format(&feature.url) {
             Some("bed") if *track_type == "bed".to_string() => {
-                vec.push(giggle(feature, &coord, chr_prefix))
+                vec.push(bed_simple(feature, &coord, chr_prefix, &database.beds))
             }
             Some("bb") if *track_type == "bed".to_string() => {
                 vec.push(libbigbed_simple(feature, &coord, chr_prefix))
             }
             Some("bw") if *track_type == "wig".to_string() => {
                 if let Some(bins) = bins {
-                    vec.push(libbigwig_stats(feature, &coord, chr_prefix, bins));
+                    vec.push(libbigwig_stats(feature, &coord, chr_prefix, bins, stat));
                 } else {
                     vec.push(libbigwig_simple(feature, &coord, chr_prefix));
                 }
@@ -112,19 +186,25 @@ pub fn region_to_feature(
 }
 
 pub fn region_to_feature_map(
-    config: &Arc<Config>,
+    data: &ConfigData,
+    database: &Database,
     track_type: &String,
     coord: Region,
     bins: Option<u32>,
+    stat: BigWigStat,
 ) -> HashMap<String, Vec<Feature>> {
+    let bins = bin_count(bins, coord.stop.saturating_sub(coord.start));
     let mut vec: HashMap<String, Vec<Feature>> = HashMap::new();
-    for feature in config.data[0].features.iter() {
+    for feature in data.features.iter() {
         let path = Path::new(&feature.url);
         let chr_prefix = feature.chr_prefix.clone().unwrap_or("".to_string());
 
-        match path.extension().unwrap().to_str() {
+        match feature_format(&feature.url) {
             Some("bed") if *track_type == "bed".to_string() => {
-                vec.insert(feature.url.clone(), giggle(feature, &coord, chr_prefix));
+                vec.insert(
+                    feature.url.clone(),
+                    bed_simple(feature, &coord, chr_prefix, &database.beds),
+                );
             }
             Some("bb") if *track_type == "bed".to_string() => {
                 vec.insert(
@@ -136,7 +216,7 @@ pub fn region_to_feature_map(
                 if let Some(bins) = bins {
                     vec.insert(
                         feature.url.clone(),
-                        libbigwig_stats(feature, &coord, chr_prefix, bins),
+                        libbigwig_stats(feature, &coord, chr_prefix, bins, stat),
                     );
                 } else {
                     vec.insert(
@@ -183,10 +263,20 @@ fn node_id_to_feature_old(
 }
 */
 
+// Paths of libBigWig; a chromosome with NUL bytes, which comes from a request, matches nothing.
+fn c_paths(path: &String, chrom: String) -> Option<(CString, CString)> {
+    match (CString::new(path.clone()), CString::new(chrom)) {
+        (Ok(path), Ok(chrom)) => Some((path, chrom)),
+        _ => None,
+    }
+}
+
 fn libbigbed_simple(feature: &ConfigFeature, coord: &Region, prefix: String) -> Vec<Feature> {
     let path = &feature.url;
-    let path_loc = CString::new(path.clone()).unwrap();
-    let path_str = CString::new(prefix + coord.path.as_ref()).unwrap();
+    let (path_loc, path_str) = match c_paths(path, prefix + coord.path.as_ref()) {
+        Some(paths) => paths,
+        None => return vec![],
+    };
 
     let mut vec: Vec<Feature> = vec![];
     unsafe {
@@ -209,9 +299,8 @@ fn libbigbed_simple(feature: &ConfigFeature, coord: &Region, prefix: String) ->
                 let start_offset = *(*intervals).start.offset(i as isize) as u64;
                 let stop_offset = *(*intervals).end.offset(i as isize) as u64;
                 let name = CStr::from_ptr(*(*intervals).str.offset(i as isize))
-                    .to_str()
-                    .unwrap()
-                    .to_owned();
+                    .to_string_lossy()
+                    .into_owned();
                 let splitted_attr = name.split("\t").map(|s| s.to_string()).collect();
                 vec.push(Feature {
                     start_offset: start_offset,
@@ -238,8 +327,10 @@ fn libbigbed_simple(feature: &ConfigFeature, coord: &Region, prefix: String) ->
 fn libbigbed(feature: &ConfigFeature, coord: &Region, prefix: String) -> Vec<Feature> {
     let path = &feature.url;
     debug!("{:?} {:?}", path, coord);
-    let path_loc = CString::new(path.clone()).unwrap();
-    let path_str = CString::new(prefix + coord.path.as_ref()).unwrap();
+    let (path_loc, path_str) = match c_paths(path, prefix + coord.path.as_ref()) {
+        Some(paths) => paths,
+        None => return vec![],
+    };
     let mut vec: Vec<Feature> = vec![];
     unsafe {
         if bwInit(1 << 17) != 0 {
@@ -269,9 +360,8 @@ fn libbigbed(feature: &ConfigFeature, coord: &Region, prefix: String) -> Vec<Fea
                     coord.stop - *(*intervals).end.offset(i as isize) as u64
                 };
                 let name = CStr::from_ptr(*(*intervals).str.offset(i as isize))
-                    .to_str()
-                    .unwrap()
-                    .to_owned();
+                    .to_string_lossy()
+                    .into_owned();
                 let splitted_attr = name.split("\t").map(|s| s.to_string()).collect();
                 vec.push(Feature {
                     start_offset: start_offset,
@@ -295,9 +385,84 @@ fn libbigbed(feature: &ConfigFeature, coord: &Region, prefix: String) -> Vec<Fea
 
 #[cfg(test)]
 mod tests {
-    use super::libbigbed;
+    use super::{bin_count, libbigbed, libbigwig_stats, BigWigStat, MAX_BINS};
     use crate::features::Feature;
     use crate::lib::{ConfigFeature, Region};
+    use serde_json;
+
+    fn tiny_stats(start: u64, stop: u64, bins: u32, stat: BigWigStat) -> Vec<Feature> {
+        libbigwig_stats(
+            &ConfigFeature {
+                name: "tiny".to_owned(),
+                url: "test/graph/tiny.bw".to_owned(),
+                chr_prefix: None,
+                viz: None,
+            },
+            &Region {
+                path: "chr1".to_owned(),
+                start: start,
+                stop: stop,
+            },
+            "".to_owned(),
+            bins,
+            stat,
+        )
+    }
+
+    #[test]
+    fn bin_count_works() {
+        assert_eq!(bin_count(None, 100), None);
+        assert_eq!(bin_count(Some(4), 100), Some(4));
+        assert_eq!(bin_count(Some(0), 100), Some(1));
+        assert_eq!(bin_count(Some(100), 10), Some(10));
+        assert_eq!(bin_count(Some(100000), 1000000), Some(MAX_BINS));
+        assert_eq!(bin_count(None, 1000000), Some(MAX_BINS));
+    }
+
+    #[test]
+    fn libbigwig_stats_works() {
+        // test/graph/tiny.bedGraph: chr1 0-2 is 1, 2-4 is 3 and 6-8 is 2.
+        let bins = tiny_stats(0, 10, 5, BigWigStat::Mean);
+        let bounds: Vec<(u64, u64)> = bins
+            .iter()
+            .map(|t| (t.start_offset, t.stop_offset))
+            .collect();
+        assert_eq!(bounds, vec![(0, 2), (2, 4), (4, 6), (6, 8), (8, 10)]);
+        let values: Vec<Option<f32>> = bins.iter().map(|t| t.value).collect();
+        assert_eq!(values, vec![Some(1.0), Some(3.0), None, Some(2.0), None]);
+        assert_eq!(
+            serde_json::to_value(&bins[2]).unwrap()["value"],
+            serde_json::Value::Null
+        );
+
+        let bounds: Vec<(u64, u64)> = tiny_stats(0, 10, 3, BigWigStat::Mean)
+            .iter()
+            .map(|t| (t.start_offset, t.stop_offset))
+            .collect();
+        assert_eq!(bounds, vec![(0, 3), (3, 6), (6, 10)]);
+    }
+
+    #[test]
+    fn bigwig_stat_works() {
+        assert_eq!(BigWigStat::new(None), Ok(BigWigStat::Mean));
+        assert_eq!(BigWigStat::new(Some("max")), Ok(BigWigStat::Max));
+        assert!(BigWigStat::new(Some("median")).is_err());
+
+        // bwStats returns doubles: chr1 0-2 is 1, 2-4 is 3 and 4-5 has no data.
+        let value = |stat| {
+            tiny_stats(0, 5, 1, stat)
+                .iter()
+                .map(|t| t.value.unwrap())
+                .next()
+                .unwrap()
+        };
+        assert_eq!(value(BigWigStat::Mean), 2.0);
+        assert_eq!(value(BigWigStat::Max), 3.0);
+        assert_eq!(value(BigWigStat::Min), 1.0);
+        assert_eq!(value(BigWigStat::Sum), 8.0);
+        assert!((value(BigWigStat::Cov) - 0.8).abs() < 1e-6);
+        assert!((value(BigWigStat::Std) - 1.1547).abs() < 1e-4);
+    }
 
     #[test]
     fn it_doesnot_work() {
@@ -362,11 +527,14 @@ fn libbigwig_stats(
     coord: &Region,
     prefix: String,
     bins: u32,
+    stat: BigWigStat,
 ) -> Vec<Feature> {
     let path = &feature.url;
-    let path_loc = CString::new(path.clone()).unwrap();
+    let (path_loc, path_str) = match c_paths(path, prefix + coord.path.as_ref()) {
+        Some(paths) => paths,
+        None => return vec![],
+    };
     let read_only = CString::new("r").unwrap();
-    let path_str = CString::new(prefix + coord.path.as_ref()).unwrap();
     let mut vec: Vec<Feature> = vec![];
     unsafe {
         if bwInit(1 << 17) != 0 {
@@ -382,26 +550,27 @@ fn libbigwig_stats(
             coord.start as u32,
             coord.stop as u32,
             bins,
-            0 as i32, // Average
+            stat.code(),
         );
         if !intervals.is_null() {
+            // bwStats returns a double for each bin, which is NaN where the bin has no data.
             let len = bins as usize;
-            let ptr = intervals as *const f32;
+            let ptr = intervals as *const f64;
             let slice = std::slice::from_raw_parts(ptr, len);
             for i in 0..bins {
-                //let start_offset =
-                //    *(*intervals).start.offset(i as isize) as u64;
-                //let stop_offset =
-                //    *(*intervals).end.offset(i as isize) as u64;
-                //let value = *(*intervals).value.offset(i as isize);
+                let (start, stop) = bin_bounds(coord, bins, i);
+                let value = slice[i as usize];
                 vec.push(Feature {
-                    start_offset: coord.start,
-                    stop_offset: coord.stop,
+                    start_offset: start,
+                    stop_offset: stop,
                     id: i as u64,
                     name: feature.name.clone(),
                     attributes: vec![],
                     is_reverse: None,
-                    value: Some(slice[i as usize]),
+                    value: match value.is_nan() {
+                        true => None,
+                        false => Some(value as f32),
+                    },
                 });
             }
         }
@@ -417,9 +586,11 @@ fn libbigwig_stats(
 
 fn libbigwig_simple(feature: &ConfigFeature, coord: &Region, prefix: String) -> Vec<Feature> {
     let path = &feature.url;
-    let path_loc = CString::new(path.clone()).unwrap();
+    let (path_loc, path_str) = match c_paths(path, prefix + coord.path.as_ref()) {
+        Some(paths) => paths,
+        None => return vec![],
+    };
     let read_only = CString::new("r").unwrap();
-    let path_str = CString::new(prefix + coord.path.as_ref()).unwrap();
     let mut vec: Vec<Feature> = vec![];
     unsafe {
         if bwInit(1 << 17) != 0 {
@@ -464,9 +635,11 @@ fn libbigwig_simple(feature: &ConfigFeature, coord: &Region, prefix: String) ->
 
 fn libbigwig(feature: &ConfigFeature, coord: &Region, prefix: String) -> Vec<Feature> {
     let path = &feature.url;
-    let path_loc = CString::new(path.clone()).unwrap();
+    let (path_loc, path_str) = match c_paths(path, prefix + coord.path.as_ref()) {
+        Some(paths) => paths,
+        None => return vec![],
+    };
     let read_only = CString::new("r").unwrap();
-    let path_str = CString::new(prefix + coord.path.as_ref()).unwrap();
     let mut vec: Vec<Feature> = vec![];
     unsafe {
         if bwInit(1 << 17) != 0 {
@@ -485,16 +658,10 @@ fn libbigwig(feature: &ConfigFeature, coord: &Region, prefix: String) -> Vec<Fea
         if !intervals.is_null() {
             for i in 0..(*intervals).l {
                 let start_offset =
-                    if *(*intervals).start.offset(i as isize) - coord.start as u32 <= 0 {
-                        0
-                    } else {
-                        *(*intervals).start.offset(i as isize) as u64 - coord.start
-                    };
-                let stop_offset = if coord.stop as u32 - *(*intervals).end.offset(i as isize) <= 0 {
-                    0
-                } else {
-                    coord.stop - *(*intervals).end.offset(i as isize) as u64
-                };
+                    (*(*intervals).start.offset(i as isize) as u64).saturating_sub(coord.start);
+                let stop_offset = coord
+                    .stop
+                    .saturating_sub(*(*intervals).end.offset(i as isize) as u64);
                 let value = *(*intervals).value.offset(i as isize);
                 vec.push(Feature {
                     start_offset: start_offset,
@@ -516,7 +683,3 @@ fn libbigwig(feature: &ConfigFeature, coord: &Region, prefix: String) -> Vec<Fea
     }
     return vec;
 }
-
-fn giggle(_feature: &ConfigFeature, _coord: &Region, _prefix: String) -> Vec<Feature> {
-    return vec![];
-}
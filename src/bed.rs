@@ -0,0 +1,289 @@
+use crate::features::Feature;
+use crate::lib::{Config, ConfigFeature, Region};
+use bio::data_structures::interval_tree::IntervalTree;
+use flate2::read::MultiGzDecoder;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+// Feature url to its index.
+pub type BedDB = HashMap<String, BedIndex>;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct BedEntry {
+    pub start: u64,
+    pub stop: u64,
+    pub is_reverse: Option<bool>,
+    pub blocks: Vec<(u64, u64)>, // Absolute coordinates of BED12 blocks, empty if absent.
+    pub attributes: Vec<String>, // Columns after "end", as bigBed returns them.
+}
+
+impl BedEntry {
+    fn overlaps(&self, start: u64, stop: u64) -> bool {
+        if self.stop <= start || stop <= self.start {
+            return false;
+        }
+        self.blocks.is_empty() || self.blocks.iter().any(|b| b.0 < stop && start < b.1)
+    }
+}
+
+// Entries are sorted by start position on each chromosome, and an interval tree of each
+// chromosome holds the indexes of its entries.
+#[derive(Debug)]
+pub struct BedIndex {
+    chroms: HashMap<String, Vec<BedEntry>>,
+    trees: HashMap<String, IntervalTree<u64, usize>>,
+}
+
+impl PartialEq for BedIndex {
+    fn eq(&self, other: &BedIndex) -> bool {
+        self.chroms == other.chroms
+    }
+}
+
+impl BedIndex {
+    pub fn new() -> BedIndex {
+        BedIndex {
+            chroms: HashMap::new(),
+            trees: HashMap::new(),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<BedIndex, Box<dyn Error>> {
+        let file = File::open(path)?;
+        // Bgzip is a series of gzip members, so the whole stream can be decoded sequentially.
+        let reader: Box<dyn Read> = match path.extension().and_then(|t| t.to_str()) {
+            Some("gz") | Some("bgz") => Box::new(MultiGzDecoder::new(file)),
+            _ => Box::new(file),
+        };
+        let mut index = BedIndex::new();
+        for line in BufReader::new(reader).lines() {
+            let line = line?;
+            if line.is_empty()
+                || line.starts_with("#")
+                || line.starts_with("track")
+                || line.starts_with("browser")
+            {
+                continue;
+            }
+            match BedIndex::parse_line(&line) {
+                Ok((chrom, entry)) => index.insert(chrom, entry),
+                Err(e) => debug!("ignoring line {:?}: {}", line, e),
+            }
+        }
+        index.build();
+        Ok(index)
+    }
+
+    fn parse_line(line: &str) -> Result<(String, BedEntry), Box<dyn Error>> {
+        let columns: Vec<&str> = line.split("\t").collect();
+        if columns.len() < 3 {
+            return Err(From::from("Less than 3 columns"));
+        }
+        let start = columns[1].parse::<u64>()?;
+        let stop = columns[2].parse::<u64>()?;
+        if stop < start {
+            return Err(From::from("The end is before the start"));
+        }
+        let is_reverse = match columns.get(5) {
+            Some(&"+") => Some(false),
+            Some(&"-") => Some(true),
+            _ => None,
+        };
+        let mut blocks = vec![];
+        if columns.len() >= 12 {
+            let sizes = columns[10].split(",").filter(|t| !t.is_empty());
+            let starts = columns[11].split(",").filter(|t| !t.is_empty());
+            for (size, block_start) in sizes.zip(starts) {
+                let block_start = start + block_start.parse::<u64>()?;
+                blocks.push((block_start, block_start + size.parse::<u64>()?));
+            }
+        }
+        Ok((
+            columns[0].to_string(),
+            BedEntry {
+                start: start,
+                stop: stop,
+                is_reverse: is_reverse,
+                blocks: blocks,
+                attributes: columns[3..].iter().map(|t| t.to_string()).collect(),
+            },
+        ))
+    }
+
+    fn insert(&mut self, chrom: String, entry: BedEntry) {
+        self.chroms.entry(chrom).or_insert(vec![]).push(entry);
+    }
+
+    fn build(&mut self) {
+        for (chrom, entries) in self.chroms.iter_mut() {
+            entries.sort_by_key(|t| (t.start, t.stop));
+            let mut tree = IntervalTree::new();
+            for (i, entry) in entries.iter().enumerate() {
+                tree.insert(entry.start..entry.stop, i);
+            }
+            self.trees.insert(chrom.clone(), tree);
+        }
+    }
+
+    // Entries overlapping [start, stop), in the order of their start positions.
+    pub fn query(&self, chrom: &str, start: u64, stop: u64) -> Vec<&BedEntry> {
+        let (entries, tree) = match (self.chroms.get(chrom), self.trees.get(chrom)) {
+            (Some(entries), Some(tree)) if start < stop => (entries, tree),
+            _ => return vec![],
+        };
+        let mut found: Vec<usize> = tree.find(start..stop).map(|t| *t.data()).collect();
+        found.sort();
+        found
+            .into_iter()
+            .map(|i| &entries[i])
+            .filter(|t| t.overlaps(start, stop))
+            .collect()
+    }
+}
+
+pub fn is_bed(url: &String) -> bool {
+    url.ends_with(".bed") || url.ends_with(".bed.gz") || url.ends_with(".bed.bgz")
+}
+
+// Index every BED feature of every dataset.
+pub fn build_bed_db(config: &Config) -> BedDB {
+    let mut beds = BedDB::new();
+    for data in config.data.iter() {
+        for feature in data.features.iter() {
+            if !is_bed(&feature.url) || beds.contains_key(&feature.url) {
+                continue;
+            }
+            info!("Indexing:  {:?}", feature.url);
+            match BedIndex::from_file(Path::new(&feature.url)) {
+                Ok(index) => {
+                    beds.insert(feature.url.clone(), index);
+                }
+                Err(e) => debug!("could not open {}; skipping.", e),
+            }
+        }
+    }
+    beds
+}
+
+// Features overlapping the region, in absolute coordinates.
+pub fn bed_simple(
+    feature: &ConfigFeature,
+    coord: &Region,
+    prefix: String,
+    beds: &BedDB,
+) -> Vec<Feature> {
+    let index = match beds.get(&feature.url) {
+        Some(index) => index,
+        None => return vec![],
+    };
+    index
+        .query(&(prefix + coord.path.as_ref()), coord.start, coord.stop)
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| Feature {
+            start_offset: entry.start,
+            stop_offset: entry.stop,
+            id: i as u64,
+            name: feature.name.clone(),
+            is_reverse: entry.is_reverse,
+            attributes: entry.attributes.clone(),
+            value: None,
+        })
+        .collect()
+}
+
+// Features overlapping the region, as offsets from both ends of the region.
+pub fn bed(feature: &ConfigFeature, coord: &Region, prefix: String, beds: &BedDB) -> Vec<Feature> {
+    let index = match beds.get(&feature.url) {
+        Some(index) => index,
+        None => return vec![],
+    };
+    index
+        .query(&(prefix + coord.path.as_ref()), coord.start, coord.stop)
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| Feature {
+            start_offset: entry.start.saturating_sub(coord.start),
+            stop_offset: coord.stop.saturating_sub(entry.stop),
+            id: i as u64,
+            name: feature.name.clone(),
+            is_reverse: entry.is_reverse,
+            attributes: entry.attributes.clone(),
+            value: None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_index() -> BedIndex {
+        BedIndex::from_file(Path::new("test/bed/test.bed")).unwrap()
+    }
+
+    #[test]
+    fn query_works() {
+        let index = test_index();
+        let starts = |chrom, start, stop| -> Vec<u64> {
+            index
+                .query(chrom, start, stop)
+                .iter()
+                .map(|t| t.start)
+                .collect()
+        };
+        assert_eq!(starts("chr1", 0, 100), Vec::<u64>::new());
+        assert_eq!(starts("chr1", 1000, 1001), vec![1000]);
+        assert_eq!(starts("chr1", 1500, 5100), vec![1000, 5000]);
+        // A long interval is found even if it starts far before the query.
+        assert_eq!(starts("chr2", 90000, 90010), vec![100]);
+        assert_eq!(starts("chrX", 0, 100000), Vec::<u64>::new());
+        assert_eq!(starts("chr1", 1001, 1000), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn bed12_blocks_work() {
+        let index = test_index();
+        // The intron of the BED12 entry does not overlap.
+        assert_eq!(index.query("chr3", 250, 300).len(), 0);
+        assert_eq!(index.query("chr3", 150, 160).len(), 1);
+        assert_eq!(index.query("chr3", 350, 360).len(), 1);
+        let entry = index.query("chr3", 350, 360)[0];
+        assert_eq!(entry.blocks, vec![(100, 200), (300, 400)]);
+        assert_eq!(entry.is_reverse, Some(true));
+    }
+
+    #[test]
+    fn bgzip_works() {
+        let gz = BedIndex::from_file(Path::new("test/bed/test.bed.gz")).unwrap();
+        assert_eq!(gz, test_index());
+    }
+
+    #[test]
+    fn bed_feature_works() {
+        let feature = ConfigFeature {
+            name: "test".to_owned(),
+            url: "test/bed/test.bed".to_owned(),
+            chr_prefix: Some("chr".to_owned()),
+            viz: None,
+        };
+        let mut beds = BedDB::new();
+        beds.insert(feature.url.clone(), test_index());
+        let region = Region {
+            path: "1".to_owned(),
+            start: 1500,
+            stop: 5100,
+        };
+        let features = bed(&feature, &region, "chr".to_owned(), &beds);
+        assert_eq!(features.len(), 2);
+        assert_eq!(features[0].start_offset, 0);
+        assert_eq!(features[0].stop_offset, 3100);
+        assert_eq!(features[0].is_reverse, Some(false));
+        assert_eq!(features[0].attributes[0], "gene1");
+        assert_eq!(features[1].start_offset, 3500);
+        assert_eq!(features[1].stop_offset, 0);
+    }
+}
@@ -0,0 +1,753 @@
+use crate::fasta::write_fasta;
+use crate::gfa::write_gfa;
+use crate::graph::{GraphFormat, VgGraph};
+use crate::jobs::Jobs;
+use crate::lib::{Config, ConfigData, Database, OptionalRegion};
+use crate::render::{render_svg, to_dot};
+use crate::utils::time;
+use crate::vg::{DEFAULT_STEP, MAX_STEP};
+use crate::Args;
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Write};
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub files: u64,
+    pub bytes: u64,
+    pub budget: u64,
+    pub ttl: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub evicted_files: u64,
+    pub evicted_bytes: u64,
+    pub last_sweep: Option<u64>,
+}
+
+// A generation in progress, which identical requests wait for.
+struct InFlight {
+    result: Mutex<Option<Result<bool, (ErrorKind, String)>>>,
+    done: Condvar,
+}
+
+struct CacheEntry {
+    path: PathBuf,
+    bytes: u64,
+    accessed: u64,
+}
+
+// Keeps `--tmp` within a byte budget. Files not accessed for `ttl` seconds are removed,
+// then the least recently used ones until the rest fits in `budget` bytes.
+// Only cached graphs and uploads under `xg/` whose jobs are over are managed;
+// anything else under `--tmp`, e.g. job records or files in progress, is kept.
+pub struct CacheManager {
+    dir: PathBuf,
+    budget: u64,
+    ttl: u64,
+    jobs: Arc<Jobs>,
+    accessed: Mutex<HashMap<PathBuf, u64>>,
+    stats: Mutex<CacheStats>,
+    in_flight: Mutex<HashMap<String, Arc<InFlight>>>,
+}
+
+impl CacheManager {
+    pub fn new(dir: &Path, budget: u64, ttl: u64, jobs: Arc<Jobs>) -> CacheManager {
+        CacheManager {
+            dir: dir.to_path_buf(),
+            budget: budget,
+            ttl: ttl,
+            jobs: jobs,
+            accessed: Mutex::new(HashMap::new()),
+            stats: Mutex::new(CacheStats {
+                budget: budget,
+                ttl: ttl,
+                ..CacheStats::default()
+            }),
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Run `generate` once for concurrent calls with the same key; the others wait for its result.
+    pub fn coalesce<F>(&self, key: &str, generate: F) -> Result<bool, Error>
+    where
+        F: FnOnce() -> Result<bool, Error>,
+    {
+        let (in_flight, first) = {
+            let mut map = self.in_flight.lock().unwrap();
+            match map.get(key) {
+                Some(in_flight) => (in_flight.clone(), false),
+                None => {
+                    let in_flight = Arc::new(InFlight {
+                        result: Mutex::new(None),
+                        done: Condvar::new(),
+                    });
+                    map.insert(key.to_string(), in_flight.clone());
+                    (in_flight, true)
+                }
+            }
+        };
+        if first {
+            // Waiters are released even if the generation panics.
+            let result = panic::catch_unwind(AssertUnwindSafe(generate));
+            *in_flight.result.lock().unwrap() = Some(match result {
+                Ok(ref result) => result
+                    .as_ref()
+                    .map(|t| *t)
+                    .map_err(|e| (e.kind(), e.to_string())),
+                Err(_) => Err((ErrorKind::Other, "The generation panicked".to_string())),
+            });
+            self.in_flight.lock().unwrap().remove(key);
+            in_flight.done.notify_all();
+            return result.unwrap_or_else(|e| panic::resume_unwind(e));
+        }
+        debug!("Waiting for the generation of {}", key);
+        let mut result = in_flight.result.lock().unwrap();
+        while result.is_none() {
+            result = in_flight.done.wait(result).unwrap();
+        }
+        match *result {
+            Some(Ok(generated)) => Ok(generated),
+            Some(Err((kind, ref e))) => Err(Error::new(kind, e.clone())),
+            None => unreachable!(),
+        }
+    }
+
+    // Record a lookup of a cached file.
+    pub fn hit(&self, path: &Path) {
+        self.stats.lock().unwrap().hits += 1;
+        self.touch(path);
+    }
+
+    pub fn miss(&self) {
+        self.stats.lock().unwrap().misses += 1;
+    }
+
+    pub fn touch(&self, path: &Path) {
+        self.accessed
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), time());
+    }
+
+    // Whether the file is one to be evicted: a cached graph, or an upload whose job is over.
+    fn is_managed(&self, name: &str, upload: bool) -> bool {
+        if name.starts_with('.') {
+            return false;
+        }
+        match upload {
+            // Files of an upload are named after its uuid, e.g. `<uuid>.xg`.
+            true => !self.jobs.is_active(name.split('.').next().unwrap_or(name)),
+            false => {
+                let extension = Path::new(name).extension().and_then(|t| t.to_str());
+                GraphFormat::all()
+                    .iter()
+                    .any(|t| Some(t.extension()) == extension)
+            }
+        }
+    }
+
+    // Managed files, with when they were last accessed or modified.
+    fn entries(&self) -> Vec<CacheEntry> {
+        let accessed = self.accessed.lock().unwrap();
+        let mut entries = vec![];
+        for &(ref dir, upload) in [(self.dir.clone(), false), (self.dir.join("xg"), true)].iter() {
+            let read_dir = match fs::read_dir(dir) {
+                Ok(read_dir) => read_dir,
+                Err(_) => continue,
+            };
+            for entry in read_dir.filter_map(|t| t.ok()) {
+                let path = entry.path();
+                let metadata = match entry.metadata() {
+                    Ok(metadata) => metadata,
+                    Err(_) => continue,
+                };
+                let managed = path
+                    .file_name()
+                    .and_then(|t| t.to_str())
+                    .map(|t| self.is_managed(t, upload))
+                    .unwrap_or(false);
+                if !metadata.is_file() || !managed {
+                    continue;
+                }
+                let modified = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map(|t| t.as_secs())
+                    .unwrap_or(0);
+                entries.push(CacheEntry {
+                    accessed: accessed.get(&path).cloned().unwrap_or(modified),
+                    bytes: metadata.len(),
+                    path: path,
+                });
+            }
+        }
+        entries
+    }
+
+    pub fn sweep(&self) {
+        let mut entries = self.entries();
+        entries.sort_by_key(|t| t.accessed);
+        let now = time();
+        let mut bytes: u64 = entries.iter().map(|t| t.bytes).sum();
+        let mut files = entries.len() as u64;
+        let mut evicted_files = 0;
+        let mut evicted_bytes = 0;
+        for entry in entries.iter() {
+            let expired = self.ttl > 0 && entry.accessed + self.ttl < now;
+            if !expired && bytes <= self.budget {
+                continue;
+            }
+            match fs::remove_file(&entry.path) {
+                Ok(_) => {
+                    debug!("Evicted {:?}", entry.path);
+                    self.accessed.lock().unwrap().remove(&entry.path);
+                    bytes -= entry.bytes;
+                    files -= 1;
+                    evicted_files += 1;
+                    evicted_bytes += entry.bytes;
+                }
+                Err(e) => debug!("could not evict {:?}: {}", entry.path, e),
+            }
+        }
+        let mut stats = self.stats.lock().unwrap();
+        stats.files = files;
+        stats.bytes = bytes;
+        stats.evicted_files += evicted_files;
+        stats.evicted_bytes += evicted_bytes;
+        stats.last_sweep = Some(now);
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.entries();
+        let mut stats = self.stats.lock().unwrap().clone();
+        stats.files = entries.len() as u64;
+        stats.bytes = entries.iter().map(|t| t.bytes).sum();
+        stats
+    }
+}
+
+// Sweep the cache every `interval` seconds in background.
+pub fn start(cache: &Arc<CacheManager>, interval: u64) {
+    let cache = cache.clone();
+    thread::spawn(move || loop {
+        cache.sweep();
+        thread::sleep(Duration::from_secs(interval));
+    });
+}
+
+// The xg of an uploaded graph, relative to the working directory unless `--tmp` is absolute.
+pub fn uploaded_xg(args: &Args, uuid: &String) -> String {
+    format!("{}/xg/{}.xg", &args.flag_tmp, uuid)
+}
+
+// Identifies the graph files the output is generated from by their size and modification time,
+// so that cached graphs are invalidated when the files are replaced.
+pub fn graph_identity(
+    data: &ConfigData,
+    version: i32,
+    args: &Args,
+    region: &OptionalRegion,
+    uuid: &Option<String>,
+    gam: bool,
+) -> String {
+    let files: Vec<String> = match *uuid {
+        Some(ref uuid) => vec![uploaded_xg(args, uuid)],
+        None => {
+            let source = &data.source;
+            let gam = if gam { source.gam.clone() } else { None };
+            vec![
+                Some(source.xg.clone()),
+                source.json.clone(),
+                source.gfa.clone(),
+                gam,
+            ]
+            .into_iter()
+            .filter_map(|t| t)
+            .map(|t| t.replace("{}", &region.path))
+            .collect()
+        }
+    };
+    let mut identity = format!("{}\t{}", data.name, version);
+    for file in files.iter() {
+        let stamp = fs::metadata(file).ok().map(|t| {
+            let modified = t
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .unwrap_or_default();
+            format!(
+                "{}:{}.{}",
+                t.len(),
+                modified.as_secs(),
+                modified.subsec_nanos()
+            )
+        });
+        identity += &format!("\t{}={}", file, stamp.unwrap_or("missing".to_string()));
+    }
+    identity
+}
+
+// 64-bit FNV-1a, which unlike `DefaultHasher` stays the same across Rust releases,
+// so that cached files outlive toolchain updates.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// The file name under `--tmp` that `/graph` looks up.
+// It is addressed by a hash of every parameter affecting the output and the graph identity,
+// written out as `key=value` lines.
+pub fn graph_cache_filename(
+    format: GraphFormat,
+    uuid: &Option<String>,
+    region: &OptionalRegion,
+    steps: &Option<i64>,
+    gam: bool,
+    downsampled: bool,
+    identity: &str,
+) -> String {
+    let key = [
+        format!("format={}{}", format.prefix(), format.extension()),
+        format!("uuid={}", uuid.as_ref().map(|t| t.as_str()).unwrap_or("")),
+        format!("region={}", region),
+        format!("steps={}", steps.unwrap_or(DEFAULT_STEP).min(MAX_STEP)),
+        format!("gam={}", format == GraphFormat::Raw && gam),
+        format!("downsampled={}", downsampled),
+        format!("identity={}", identity),
+    ]
+    .join("\n");
+    format!(
+        "{}{}{}_{:016x}.{}",
+        format.prefix(),
+        uuid.clone().map(|t| t + "_").unwrap_or("".to_string()),
+        region.uuid(),
+        fnv1a(key.as_bytes()),
+        format.extension()
+    )
+}
+
+static TMP_SERIAL: AtomicUsize = AtomicUsize::new(0);
+
+// Generate the graph of the region into the cache file, as raw vg JSON, a sankey, GFA or SVG.
+// A region longer than the max interval of the dataset is an error unless `downsample` is set.
+pub fn generate_graph_cache(
+    config: &Config,
+    data: &ConfigData,
+    database: &Database,
+    args: &Args,
+    region: OptionalRegion,
+    steps: &Option<i64>,
+    format: GraphFormat,
+    gam: bool,
+    downsample: bool,
+    uuid: &Option<String>,
+    cache_path: &Path,
+) -> Result<bool, Error> {
+    // Written to a temporary file and renamed, so that readers never see a partial file.
+    let tmp_path = PathBuf::from(format!(
+        "{}.{}.{}.tmp",
+        cache_path.display(),
+        process::id(),
+        TMP_SERIAL.fetch_add(1, Ordering::SeqCst)
+    ));
+    let cache_file = File::create(&tmp_path)?;
+    let graph = database.graph.as_graph();
+    let (xg, tmp) = match *uuid {
+        Some(ref uuid) => (uploaded_xg(args, uuid), true),
+        None => (data.source.xg.clone(), false),
+    };
+    let max_interval = data.max_interval(&args.flag_interval);
+    let result = match format {
+        GraphFormat::Sankey => graph.generate_graph_to_file(
+            region,
+            0,
+            &cache_file,
+            steps,
+            config,
+            data,
+            database,
+            &xg,
+            tmp,
+            max_interval,
+            downsample,
+        ),
+        GraphFormat::Raw => graph.generate_graph_to_file_wo_helper(
+            region,
+            0,
+            &tmp_path,
+            steps,
+            config,
+            data,
+            database,
+            &xg,
+            tmp,
+            max_interval,
+            downsample,
+            gam,
+            database.version,
+            args.flag_vg_threads,
+        ),
+        // Converted from the raw graph, without alignments.
+        GraphFormat::Gfa | GraphFormat::Svg | GraphFormat::Fasta => {
+            let reference = region.path.clone();
+            let raw_path = PathBuf::from(format!("{}.raw", tmp_path.display()));
+            let result = graph
+                .generate_graph_to_file_wo_helper(
+                    region,
+                    0,
+                    &raw_path,
+                    steps,
+                    config,
+                    data,
+                    database,
+                    &xg,
+                    tmp,
+                    max_interval,
+                    downsample,
+                    false,
+                    database.version,
+                    args.flag_vg_threads,
+                )
+                .and_then(|_| VgGraph::from_reader(BufReader::new(File::open(&raw_path)?)))
+                .and_then(|mut graph| match format {
+                    GraphFormat::Gfa => write_gfa(&cache_file, &graph),
+                    GraphFormat::Fasta => {
+                        graph.fill_coordinates();
+                        write_fasta(&cache_file, &graph)
+                    }
+                    _ => render_svg(config, &to_dot(&graph, &reference))
+                        .and_then(|svg| (&cache_file).write_all(&svg)),
+                })
+                .map(|_| true);
+            let _ = fs::remove_file(&raw_path);
+            result
+        }
+    };
+    match result {
+        Ok(true) => fs::rename(&tmp_path, cache_path)?,
+        _ => {
+            let _ = fs::remove_file(&tmp_path);
+        }
+    }
+    result
+}
+
+// A region, or a gene name of the reference of the dataset.
+fn resolve_region(entry: &str, data: &ConfigData, database: &Database) -> Option<OptionalRegion> {
+    let gene = database
+        .gene_name_tree
+        .get(&data.ref_id)
+        .and_then(|t| t.get(entry));
+    let path = match gene {
+        Some(region) => format!("{}", region),
+        None => entry.to_string(),
+    };
+    OptionalRegion::new_with_prefix(path, &data.chr_prefix).ok()
+}
+
+// Precompute both the raw and the sankey graphs of each line of `--list` into `--tmp`.
+// A line is a region or a gene name, optionally followed by a tab and a dataset name.
+// Returns the number of failures, where a line which is not cached counts as two.
+pub fn precompute(config: &Config, database: &Database, args: &Args) -> Result<usize, Error> {
+    fs::create_dir_all(&args.flag_tmp)?;
+    let entries: Vec<String> = BufReader::new(File::open(&args.flag_list)?)
+        .lines()
+        .collect::<Result<Vec<String>, Error>>()?
+        .into_iter()
+        .filter(|t| !t.trim().is_empty() && !t.starts_with("#"))
+        .collect();
+    let steps = Some(args.flag_steps);
+    let mut failures = 0;
+    for (i, entry) in entries.iter().enumerate() {
+        let mut columns = entry.trim().split("\t");
+        let name = columns.next().unwrap_or("");
+        let data = match config.dataset(columns.next().map(|t| t.to_string()).as_ref()) {
+            Some(data) => data,
+            None => {
+                error!("[{}/{}] {}: unknown dataset", i + 1, entries.len(), entry);
+                failures += 2;
+                continue;
+            }
+        };
+        let region = match resolve_region(name, data, database) {
+            Some(region) => region,
+            None => {
+                error!(
+                    "[{}/{}] {}: not a region nor a gene",
+                    i + 1,
+                    entries.len(),
+                    name
+                );
+                failures += 2;
+                continue;
+            }
+        };
+        if let Some(true) = region.inverted() {
+            error!(
+                "[{}/{}] {} ({}): the region is inverted",
+                i + 1,
+                entries.len(),
+                name,
+                region
+            );
+            failures += 2;
+            continue;
+        }
+        let identity = graph_identity(data, database.version, args, &region, &None, false);
+        for &format in [GraphFormat::Raw, GraphFormat::Sankey].iter() {
+            let filename =
+                graph_cache_filename(format, &None, &region, &steps, false, false, &identity);
+            let cache_path = Path::new(&args.flag_tmp).join(&filename);
+            let result = generate_graph_cache(
+                config,
+                data,
+                database,
+                args,
+                region.clone(),
+                &steps,
+                format,
+                false,
+                false,
+                &None,
+                &cache_path,
+            );
+            let status = match result {
+                Ok(true) => {
+                    info!(
+                        "[{}/{}] {} ({}) -> {}: done",
+                        i + 1,
+                        entries.len(),
+                        name,
+                        region,
+                        filename
+                    );
+                    continue;
+                }
+                Ok(false) => "failed".to_string(),
+                Err(e) => format!("failed: {}", e),
+            };
+            failures += 1;
+            let _ = fs::remove_file(&cache_path);
+            error!(
+                "[{}/{}] {} ({}) -> {}: {}",
+                i + 1,
+                entries.len(),
+                name,
+                region,
+                filename,
+                status
+            );
+        }
+    }
+    Ok(failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features;
+    use crate::gfa::Gfa;
+    use crate::vg::GraphDB;
+    use docopt::Docopt;
+    use serde_yaml;
+    use std::env;
+    use std::io::Write;
+
+    fn write(path: &Path, bytes: usize) {
+        File::create(path)
+            .unwrap()
+            .write_all(&vec![0u8; bytes])
+            .unwrap();
+    }
+
+    #[test]
+    fn cache_manager_works() {
+        let dir = env::temp_dir().join(format!("ggbb-cache-{}", time()));
+        fs::create_dir_all(dir.join("xg")).unwrap();
+        fs::create_dir_all(dir.join("jobs")).unwrap();
+        fs::create_dir_all(dir.join("rocksdb")).unwrap();
+        write(&dir.join("old.json"), 10);
+        write(&dir.join("new.json"), 10);
+        write(&dir.join(".gitkeep"), 10);
+        write(&dir.join("rocksdb").join("CURRENT"), 10);
+        write(&dir.join("xg").join("upload.xg"), 10);
+        write(&dir.join("xg").join("queued"), 10);
+        write(&dir.join("jobs").join("1.json"), 10);
+        let jobs = Arc::new(Jobs::new(&dir.join("jobs"), 1).unwrap());
+        jobs.submit("queued".to_string(), vec!["true".to_string()]);
+        let cache = CacheManager::new(&dir, 15, 0, jobs);
+        // Accessed files are kept; the rest is evicted from the least recently used.
+        cache
+            .accessed
+            .lock()
+            .unwrap()
+            .insert(dir.join("old.json"), 1);
+        cache.hit(&dir.join("new.json"));
+        cache.sweep();
+        assert!(dir.join("new.json").exists());
+        assert!(!dir.join("old.json").exists());
+        assert!(!dir.join("xg").join("upload.xg").exists());
+        // Uploads of unfinished jobs and files which are not cached graphs are kept.
+        assert!(dir.join("xg").join("queued").exists());
+        assert!(dir.join(".gitkeep").exists());
+        assert!(dir.join("rocksdb").join("CURRENT").exists());
+        assert!(dir.join("jobs").join("1.json").exists());
+        let stats = cache.stats();
+        assert_eq!(stats.files, 1);
+        assert_eq!(stats.bytes, 10);
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.evicted_files, 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn graph_identity_works() {
+        let tmp = env::temp_dir()
+            .join(format!("ggbb-identity-{}", time()))
+            .to_string_lossy()
+            .into_owned();
+        fs::create_dir_all(format!("{}/xg", tmp)).unwrap();
+        let args: Args = Docopt::new(crate::USAGE)
+            .and_then(|d| {
+                d.argv(vec!["ggbb".to_string(), format!("--tmp={}", tmp)])
+                    .deserialize()
+            })
+            .unwrap();
+        let data: ConfigData = serde_yaml::from_str(
+            "{name: tiny, ref_id: chr1, source: {xg: test/graph/tiny.xg}, chr_prefix: chr, \
+             features: [], static_files: []}",
+        )
+        .unwrap();
+        let region = OptionalRegion::new("chr1:0-4".to_string()).unwrap();
+        let uuid = Some("upload".to_string());
+        let identity = || graph_identity(&data, 0, &args, &region, &uuid, false);
+        assert!(identity().ends_with("=missing"));
+        write(Path::new(&uploaded_xg(&args, &"upload".to_string())), 10);
+        assert!(identity().contains(&format!("{}/xg/upload.xg=10:", tmp)));
+        fs::remove_dir_all(&tmp).unwrap();
+    }
+
+    #[test]
+    fn precompute_works() {
+        let tmp = env::temp_dir()
+            .join(format!("ggbb-precompute-{}", time()))
+            .to_string_lossy()
+            .into_owned();
+        fs::create_dir_all(&tmp).unwrap();
+        let list = format!("{}/cache.txt", tmp);
+        fs::write(
+            &list,
+            "# Regions to cache\nchr1:0-4\nchr1:8-2\nNOTAGENE\nchr1:0-4\tunknown\n",
+        )
+        .unwrap();
+        let args: Args = Docopt::new(crate::USAGE)
+            .and_then(|d| {
+                d.argv(vec![
+                    "ggbb".to_string(),
+                    "--cache".to_string(),
+                    format!("--tmp={}", tmp),
+                    format!("--list={}", list),
+                ])
+                .deserialize()
+            })
+            .unwrap();
+        let config: Config = serde_yaml::from_str(
+            "{bin: {vg: vg, vg_tmp: vg, graphviz: dot, fa22bit: faToTwoBit, bigbed: bedToBigBed}, \
+             reference: {chroms: static/GRCh.json, data: []}, \
+             data: [{name: tiny, ref_id: tiny, chr_prefix: chr, max_interval: 10, \
+             source: {xg: '', gfa: test/graph/tiny.gfa}, features: [], static_files: []}]}",
+        )
+        .unwrap();
+        let graph = GraphDB::Gfa(Gfa::new());
+        let database = features::tmp_new(graph, &config, format!("{}/rocksdb", tmp), &true);
+
+        // The inverted region, the unknown name and the unknown dataset fail both graphs.
+        assert_eq!(precompute(&config, &database, &args).unwrap(), 6);
+        let cached: Vec<String> = fs::read_dir(&tmp)
+            .unwrap()
+            .filter_map(|t| t.ok())
+            .map(|t| t.file_name().to_string_lossy().into_owned())
+            .filter(|t| t.ends_with(".json"))
+            .collect();
+        assert_eq!(cached.len(), 2);
+        assert!(cached.iter().all(|t| t.contains("chr1:0-4")));
+        fs::remove_dir_all(&tmp).unwrap();
+    }
+
+    #[test]
+    fn coalesce_works() {
+        let dir = env::temp_dir().join(format!("ggbb-coalesce-{}", time()));
+        let jobs = Arc::new(Jobs::new(&dir, 1).unwrap());
+        let cache = Arc::new(CacheManager::new(&env::temp_dir(), 0, 0, jobs));
+        let count = Arc::new(AtomicUsize::new(0));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let cache = cache.clone();
+                let count = count.clone();
+                thread::spawn(move || {
+                    cache.coalesce("key", || {
+                        count.fetch_add(1, Ordering::SeqCst);
+                        thread::sleep(Duration::from_millis(200));
+                        Ok(true)
+                    })
+                })
+            })
+            .collect();
+        for t in threads.into_iter() {
+            assert_eq!(t.join().unwrap().unwrap(), true);
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        // Finished generations are not reused.
+        let failed = cache.coalesce("key", || Err(Error::new(ErrorKind::Other, "failed")));
+        assert!(failed.is_err());
+    }
+
+    #[test]
+    fn graph_cache_filename_works() {
+        let region = OptionalRegion::new_with_prefix("chr1:100-200".to_string(), "chr").unwrap();
+        let filename = |format, uuid: Option<&str>, steps, gam, identity| {
+            graph_cache_filename(
+                format,
+                &uuid.map(|t| t.to_string()),
+                &region,
+                &steps,
+                gam,
+                false,
+                identity,
+            )
+        };
+        let raw = filename(GraphFormat::Raw, None, None, false, "a");
+        // Names must not change between builds, or every cached file is orphaned.
+        assert_eq!(raw, "raw_chr1:100-200_4ee01b47965566e6.json");
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert!(filename(GraphFormat::Sankey, Some("abc"), None, false, "a")
+            .starts_with(&format!("abc_{}_", region.uuid())));
+        // The default steps and gam on sankeys do not change the output.
+        assert_eq!(raw, filename(GraphFormat::Raw, None, Some(2), false, "a"));
+        assert_eq!(
+            filename(GraphFormat::Sankey, None, None, true, "a"),
+            filename(GraphFormat::Sankey, None, None, false, "a")
+        );
+        assert_ne!(raw, filename(GraphFormat::Raw, None, Some(3), false, "a"));
+        assert_ne!(raw, filename(GraphFormat::Raw, None, None, true, "a"));
+        assert_ne!(raw, filename(GraphFormat::Raw, None, None, false, "b"));
+        let gfa = filename(GraphFormat::Gfa, None, None, false, "a");
+        assert!(gfa.starts_with("gfa_") && gfa.ends_with(".gfa"));
+        assert_ne!(
+            raw,
+            graph_cache_filename(GraphFormat::Raw, &None, &region, &None, false, true, "a")
+        );
+    }
+}
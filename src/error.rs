@@ -0,0 +1,239 @@
+use iron::status;
+use iron::status::Status;
+use iron::Response;
+use serde_json;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+// Errors answered by the handlers, serialized as
+// `{"error": {"code": ..., "message": ..., "parameter": ...}}`.
+#[derive(Debug, PartialEq)]
+pub enum ApiError {
+    InvalidRequest(String),
+    MissingParameter(String),
+    InvalidParameter(String, String), // The parameter and why it is invalid.
+    InvalidRegion(String, String),    // The parameter and why the region is invalid.
+    RegionTooLarge(String, u64, u64), // The parameter, its interval and the max interval.
+    UploadTooLarge(u64),              // The max bytes of an upload.
+    Unsupported(String),
+    UnknownDataset(String),
+    UnknownFile(String),
+    UnknownGene(String, String), // The parameter and the gene name.
+    UnknownNode(String, u64),    // The parameter and the node id.
+    UnknownUuid(String),
+    UnknownJob(String),
+    Busy(String),
+    Timeout(String),
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ApiError::InvalidRequest(_) => "invalid_request",
+            ApiError::MissingParameter(_) => "missing_parameter",
+            ApiError::InvalidParameter(_, _) => "invalid_parameter",
+            ApiError::InvalidRegion(_, _) => "invalid_region",
+            ApiError::RegionTooLarge(_, _, _) => "region_too_large",
+            ApiError::UploadTooLarge(_) => "upload_too_large",
+            ApiError::Unsupported(_) => "unsupported",
+            ApiError::UnknownDataset(_) => "unknown_dataset",
+            ApiError::UnknownFile(_) => "unknown_file",
+            ApiError::UnknownGene(_, _) => "unknown_gene",
+            ApiError::UnknownNode(_, _) => "unknown_node",
+            ApiError::UnknownUuid(_) => "unknown_uuid",
+            ApiError::UnknownJob(_) => "unknown_job",
+            ApiError::Busy(_) => "busy",
+            ApiError::Timeout(_) => "timeout",
+            ApiError::Internal(_) => "internal",
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        match *self {
+            ApiError::InvalidRequest(_)
+            | ApiError::MissingParameter(_)
+            | ApiError::InvalidParameter(_, _)
+            | ApiError::InvalidRegion(_, _)
+            | ApiError::Unsupported(_) => status::BadRequest,
+            ApiError::RegionTooLarge(_, _, _) | ApiError::UploadTooLarge(_) => {
+                status::PayloadTooLarge
+            }
+            ApiError::UnknownDataset(_)
+            | ApiError::UnknownFile(_)
+            | ApiError::UnknownGene(_, _)
+            | ApiError::UnknownNode(_, _)
+            | ApiError::UnknownUuid(_)
+            | ApiError::UnknownJob(_) => status::NotFound,
+            ApiError::Busy(_) => status::ServiceUnavailable,
+            ApiError::Timeout(_) => status::GatewayTimeout,
+            ApiError::Internal(_) => status::InternalServerError,
+        }
+    }
+
+    // The request parameter which caused the error, if any.
+    pub fn parameter(&self) -> Option<&str> {
+        match *self {
+            ApiError::MissingParameter(ref param)
+            | ApiError::InvalidParameter(ref param, _)
+            | ApiError::InvalidRegion(ref param, _)
+            | ApiError::RegionTooLarge(ref param, _, _)
+            | ApiError::UnknownGene(ref param, _)
+            | ApiError::UnknownNode(ref param, _) => Some(param),
+            ApiError::UnknownDataset(_) => Some("dataset"),
+            ApiError::UnknownFile(_) => Some("filename"),
+            ApiError::UnknownUuid(_) => Some("uuid"),
+            ApiError::UnknownJob(_) => Some("id"),
+            _ => None,
+        }
+    }
+
+    pub fn response(&self) -> Response {
+        let json = json!({
+            "error": {
+                "code": self.code(),
+                "message": self.to_string(),
+                "parameter": self.parameter(),
+            }
+        });
+        Response::with((self.status(), json.to_string()))
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ApiError::InvalidRequest(ref message)
+            | ApiError::Unsupported(ref message)
+            | ApiError::Busy(ref message)
+            | ApiError::Timeout(ref message)
+            | ApiError::Internal(ref message) => write!(f, "{}", message),
+            ApiError::MissingParameter(ref param) => write!(f, "`{}` is required", param),
+            ApiError::InvalidParameter(ref param, ref message) => {
+                write!(f, "`{}` is invalid: {}", param, message)
+            }
+            ApiError::InvalidRegion(ref param, ref message) => {
+                write!(f, "`{}` is not a region: {}", param, message)
+            }
+            ApiError::RegionTooLarge(ref param, interval, max) => write!(
+                f,
+                "`{}` spans {} bp, more than the max interval {} bp",
+                param, interval, max
+            ),
+            ApiError::UploadTooLarge(max) => write!(f, "The upload is larger than {} bytes", max),
+            ApiError::UnknownDataset(ref name) => write!(f, "Unknown dataset: {}", name),
+            ApiError::UnknownFile(ref name) => write!(f, "Unknown file: {}", name),
+            ApiError::UnknownGene(_, ref name) => write!(f, "Unknown gene: {}", name),
+            ApiError::UnknownNode(_, id) => write!(f, "Unknown node: {}", id),
+            ApiError::UnknownUuid(ref uuid) => write!(f, "Unknown uuid: {}", uuid),
+            ApiError::UnknownJob(ref id) => write!(f, "Unknown job: {}", id),
+        }
+    }
+}
+
+impl Error for ApiError {}
+
+// A region longer than the max interval, carried by an `io::Error` out of the graph backends.
+#[derive(Debug, PartialEq)]
+pub struct TooLarge {
+    pub interval: u64,
+    pub max: u64,
+}
+
+impl fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "The region spans {} bp, more than the max interval {} bp",
+            self.interval, self.max
+        )
+    }
+}
+
+impl Error for TooLarge {}
+
+pub fn region_too_large(interval: u64, max: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        TooLarge {
+            interval: interval,
+            max: max,
+        },
+    )
+}
+
+impl From<io::Error> for ApiError {
+    fn from(e: io::Error) -> ApiError {
+        if let Some(t) = e.get_ref().and_then(|t| t.downcast_ref::<TooLarge>()) {
+            return ApiError::RegionTooLarge("path".to_string(), t.interval, t.max);
+        }
+        match e.kind() {
+            io::ErrorKind::TimedOut => ApiError::Timeout(e.to_string()),
+            io::ErrorKind::WouldBlock => ApiError::Busy(e.to_string()),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<Box<dyn Error>> for ApiError {
+    fn from(e: Box<dyn Error>) -> ApiError {
+        match e.downcast::<io::Error>() {
+            Ok(e) => From::from(*e),
+            Err(e) => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<String> for ApiError {
+    fn from(e: String) -> ApiError {
+        ApiError::Internal(e)
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(e: serde_json::Error) -> ApiError {
+        ApiError::Internal(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iron::response::WriteBody;
+    use serde_json::Value;
+
+    #[test]
+    fn api_error_works() {
+        let e = ApiError::RegionTooLarge("path".to_string(), 100000, 50000);
+        assert_eq!(e.status(), status::PayloadTooLarge);
+        let res = e.response();
+        assert_eq!(res.status, Some(status::PayloadTooLarge));
+        let mut buf = vec![];
+        res.body.unwrap().write_body(&mut buf).unwrap();
+        let body: Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(body["error"]["code"], "region_too_large");
+        assert_eq!(body["error"]["parameter"], "path");
+        assert_eq!(
+            body["error"]["message"],
+            "`path` spans 100000 bp, more than the max interval 50000 bp"
+        );
+
+        let timeout = io::Error::new(io::ErrorKind::TimedOut, "vg find timed out");
+        assert_eq!(ApiError::from(timeout).status(), status::GatewayTimeout);
+        assert_eq!(
+            ApiError::from(region_too_large(60000, 50000)),
+            ApiError::RegionTooLarge("path".to_string(), 60000, 50000)
+        );
+        let boxed: Box<dyn Error> = From::from("Parse Error");
+        assert_eq!(
+            ApiError::from(boxed),
+            ApiError::Internal("Parse Error".to_string())
+        );
+        assert_eq!(ApiError::Internal("".to_string()).parameter(), None);
+        assert_eq!(
+            ApiError::UploadTooLarge(1000).status(),
+            status::PayloadTooLarge
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use crate::gfa::sub_path_name;
+use crate::graph::{Mapping, Node, VgGraph};
+use bio::alphabets::dna;
+use bio::io::fasta::Writer;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Write};
+
+// The bases a mapping spells, read on the strand of `is_reverse` from its offset.
+// Edits with a sequence are substitutions or insertions, edits without bases to spell are
+// deletions, and the others match the node.
+fn mapping_sequence(mapping: &Mapping, node: &Node) -> Result<Vec<u8>, Error> {
+    let node_seq = match mapping.position.is_reverse {
+        true => dna::revcomp(node.sequence.as_bytes()),
+        false => node.sequence.as_bytes().to_vec(),
+    };
+    let offset = mapping.position.offset.unwrap_or(0) as usize;
+    let out_of_node = || {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("The mapping runs out of node {}", node.id),
+        )
+    };
+    if mapping.edit.is_empty() {
+        return node_seq
+            .get(offset..)
+            .map(|t| t.to_vec())
+            .ok_or_else(out_of_node);
+    }
+    let mut seq = vec![];
+    let mut pos = offset;
+    for edit in mapping.edit.iter() {
+        let from_length = edit.from_length.unwrap_or(0) as usize;
+        let to_length = edit.to_length.unwrap_or(0) as usize;
+        match edit.sequence {
+            Some(ref t) => seq.extend_from_slice(t.as_bytes()),
+            None if to_length == 0 => {}
+            None if to_length == from_length => seq.extend_from_slice(
+                node_seq
+                    .get(pos..pos + from_length)
+                    .ok_or_else(out_of_node)?,
+            ),
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("An edit of node {} has no sequence", node.id),
+                ))
+            }
+        }
+        pos += from_length;
+    }
+    Ok(seq)
+}
+
+// One record per path through the graph, named `path:start-stop` where the path has coordinates.
+// Without coordinates, fragments of a path are told apart as in GFA, by `path[start-end]`.
+pub fn write_fasta<W: Write>(writer: W, graph: &VgGraph) -> Result<(), Error> {
+    let nodes: HashMap<u64, &Node> = graph.node.iter().map(|t| (t.id, t)).collect();
+    let mut writer = Writer::new(writer);
+    for path in graph.path.iter() {
+        let fragments = path.fragments();
+        for mappings in fragments.iter() {
+            let mut seq = vec![];
+            let mut length = 0;
+            for mapping in mappings.iter() {
+                let node = nodes.get(&mapping.position.node_id).ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Unknown node: {}", mapping.position.node_id),
+                    )
+                })?;
+                seq.extend(mapping_sequence(mapping, node)?);
+                length += mapping.length(Some(node));
+            }
+            let id = match (
+                mappings.first().and_then(|t| t.position.coordinate),
+                fragments.len(),
+            ) {
+                (Some(start), _) => format!("{}:{}-{}", path.name, start, start + length),
+                (None, 1) => path.name.clone(),
+                (None, _) => sub_path_name(&path.name, mappings, &nodes),
+            };
+            writer.write(&id, None, &seq)?;
+        }
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{tiny_subgraph, Edit, Path, Position};
+
+    #[test]
+    fn write_fasta_works() {
+        let mut buf = vec![];
+        write_fasta(&mut buf, &tiny_subgraph()).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            ">chr1:0-10\nACGTTCCCCA\n>alt1:0-10\nACGTGCCCCA\n"
+        );
+
+        // A reverse mapping with a substitution, a deletion, and a path leaving the region.
+        let mapping = |id: u64, rank: u64, is_reverse: bool, edit: Vec<Edit>| Mapping {
+            position: Position {
+                node_id: id,
+                is_reverse: is_reverse,
+                offset: None,
+                coordinate: None,
+            },
+            edit: edit,
+            rank: Some(rank),
+        };
+        let edit = |from_length: u64, sequence: Option<&str>| Edit {
+            from_length: Some(from_length),
+            to_length: Some(sequence.map(|t| t.len() as u64).unwrap_or(from_length)),
+            sequence: sequence.map(|t| t.to_string()),
+        };
+        let deletion = |from_length: u64| Edit {
+            from_length: Some(from_length),
+            to_length: None,
+            sequence: None,
+        };
+        let graph = VgGraph {
+            node: vec![
+                Node {
+                    id: 1,
+                    sequence: "ACGT".to_string(),
+                    length: None,
+                },
+                Node {
+                    id: 4,
+                    sequence: "CCCA".to_string(),
+                    length: None,
+                },
+            ],
+            edge: vec![],
+            path: vec![Path {
+                name: "sample1".to_string(),
+                mapping: vec![
+                    mapping(
+                        1,
+                        1,
+                        true,
+                        vec![edit(2, None), edit(1, Some("T")), edit(1, None)],
+                    ),
+                    mapping(4, 5, false, vec![edit(1, None), deletion(2), edit(1, None)]),
+                ],
+                index_of_first_base: None,
+            }],
+        };
+        let mut buf = vec![];
+        write_fasta(&mut buf, &graph).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            ">sample1[0-1]\nACTT\n>sample1[4-5]\nCA\n"
+        );
+    }
+}
@@ -1,17 +1,19 @@
+use crate::bed::build_bed_db;
+use crate::graph::MemoryGraph;
 use bio::io::{bed, gff};
 use bio::utils::Strand;
 use bio::utils::Strand::*;
-use crate::lib::{Config, ConfigFeature};
+use crate::lib::{Config, ConfigData, ConfigFeature};
 use crate::lib::{Database, GeneNameEachReference, GeneNameTree, Region};
-use rocks::rocksdb::*;
-use std::collections::HashMap;
+use crate::node_index::{NodeIndex, NodeIndexWriter};
+use crate::sequence::open_twobits;
+use serde_json;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::mem::*;
+use std::io::{BufRead, BufReader, Read};
 use std::path::Path;
 use crate::vg::GraphDB;
-use crate::vg::GraphDB::VG;
 
 // NodeId to corresponding feature items.
 type Features = HashMap<u64, Vec<Feature>>;
@@ -104,96 +106,36 @@ fn record_to_nodes(
     return hash_map;
 }
 
+// The first dataset keeps the `--rocksdb` path, so that an existing index is reused.
+fn rocks_path(data: &ConfigData, index: usize, db_name: &String) -> String {
+    match data.source.rocksdb {
+        Some(ref path) => path.clone(),
+        None if index == 0 => db_name.clone(),
+        None => format!("{}.{}", db_name, data.name),
+    }
+}
+
 // tmpNew should be replecated with a novel implementation.
 // Required input list is sorted by coordinates.
 //pub fn tmp_new(graph: Arc<Graph>, config: &Config) -> Database {
 pub fn tmp_new(graph: GraphDB, config: &Config, db_name: String, rocksdb_init: &bool) -> Database {
-    let chroms = vec![
-        "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12", "13", "14", "15", "16",
-        "17", "18", "19", "20", "21", "22", "X", "Y",
-    ];
     let hashmap = CoordToNodeId::new();
-    if *rocksdb_init || !Path::new(&db_name).exists() {
-        if let Ok(cf) = DB::open(
-            &Options::default().map_db_options(|db| db.create_if_missing(true)),
-            db_name.clone(),
-        ) {
-            'iter: for chr in chroms.iter() {
-                if let Some(ref path) = config.data[0].source.node_index {
-                    let ref prefix = config.data[0].chr_prefix;
-                    let chr_name = prefix.clone() + chr;
-                    let path_string = path.clone().replace("{}", &chr_name);
-                    let path = Path::new(&path_string);
-                    debug!("Chromosome:  {:?}, {:?}", chr, path);
-
-                    let file = match File::open(path) {
-                        Ok(f) => f,
-                        Err(e) => {
-                            debug!("could not open {}; skipping.", e.description());
-                            continue 'iter;
-                        }
-                    };
-                    /*
-                    let file_gz = match extract_file(path) {
-                        Ok(f) => f,
-                        Err(e) => {continue 'iter;}
-                    };
-                    */
-
-                    let br = BufReader::new(file);
-                    let mut last_node: Option<NodeId> = None;
-                    for line in br.lines() {
-                        match line {
-                            Ok(l) => {
-                                let items: Vec<u64> =
-                                    l.split("\t").map(|a| a.parse::<u64>().unwrap()).collect();
-                                if items.len() > 1 {
-                                    if let Some(item) = last_node {
-                                        let reg = Region {
-                                            path: (*chr).to_string(),
-                                            start: item.coord,
-                                            stop: items[1],
-                                        };
-                                        let raw_bytes: [u8; 8] = unsafe { transmute(item.id) };
-                                        if let Err(err) = cf.put(
-                                            &WriteOptions::default(),
-                                            &raw_bytes,
-                                            reg.uuid().as_bytes(),
-                                        ) {
-                                            debug!("{:?} at {}", err, item.id)
-                                        }
-                                    }
-                                    last_node = Some(NodeId {
-                                        id: items[0],
-                                        coord: items[1],
-                                    });
-                                } else {
-                                    continue;
-                                }
-                            }
-                            Err(e) => {
-                                debug!("ignoring error {}", e);
-                                continue;
-                            }
-                        };
-                    }
-                    if let Some(item) = last_node {
-                        // coord.insert(item.id, Region{ path: (*chr).to_string(), start: item.coord, stop: item.coord + 1000 }); //Todo seems to wrong code.
-                        let reg = Region {
-                            path: (*chr).to_string(),
-                            start: item.coord,
-                            stop: item.coord + 1000,
-                        };
-                        let raw_bytes: [u8; 8] = unsafe { transmute(item.id) };
-                        if let Err(err) =
-                            cf.put(&WriteOptions::default(), &raw_bytes, reg.uuid().as_bytes())
-                        {
-                            debug!("{:?} at {}", err, item.id)
-                        }
+    let mut rocks = BTreeMap::new();
+    for (index, data) in config.data.iter().enumerate() {
+        let db_path = rocks_path(data, index, &db_name);
+        if *rocksdb_init || !Path::new(&db_path).exists() {
+            info!("Building node index of {} on {}", data.name, db_path);
+            match (&graph, &data.source.gfa) {
+                (&GraphDB::Gfa(ref gfa), &Some(ref file)) if !file.contains("{}") => {
+                    match gfa.load(file) {
+                        Ok(memory) => build_node_index_from_graph(data, &db_path, &memory),
+                        Err(e) => debug!("could not load {}: {}", file, e),
                     }
                 }
+                _ => build_node_index(data, &db_path, &discover_paths(&graph, config, data)),
             }
         }
+        rocks.insert(data.name.clone(), NodeIndex::open(&db_path));
     }
     let mut vec: FeatureDB = FeatureDB::new();
     let mut gene_per_ref = GeneNameEachReference::new();
@@ -218,20 +160,204 @@ pub fn tmp_new(graph: GraphDB, config: &Config, db_name: String, rocksdb_init: &
         }
         gene_per_ref.insert(data.name.clone(), gene);
     }
-    match graph {
-        VG(graph2) => {
-            let version = graph2.version(config);
-            println!("{}", version);
-            return Database {
-                features: vec,
-                //coordinates: coord,
-                rocks: db_name,
-                gene_name_tree: gene_per_ref,
-                graph: VG(graph2),
-                version: version,
+    let version = graph.as_graph().version(config);
+    println!("{}", version);
+    return Database {
+        features: vec,
+        beds: build_bed_db(config),
+        //coordinates: coord,
+        rocks: rocks,
+        twobits: open_twobits(&config.data),
+        gene_name_tree: gene_per_ref,
+        graph: graph,
+        version: version,
+    };
+}
+
+// Index reference paths, whose names start with the prefix; the first path wins on shared nodes.
+fn build_node_index_from_graph(data: &ConfigData, db_name: &String, graph: &MemoryGraph) {
+    let index = match NodeIndexWriter::create(db_name) {
+        Ok(index) => index,
+        Err(e) => {
+            debug!("could not open {}: {:?}", db_name, e);
+            return;
+        }
+    };
+    let mut indexed: HashSet<u64> = HashSet::new();
+    for name in graph.path_names() {
+        if !name.starts_with(&data.chr_prefix) {
+            continue;
+        }
+        let chr = &name[data.chr_prefix.len()..];
+        debug!("Path:  {:?}", name);
+        let nodes = graph.path_nodes(name);
+        for (i, &(id, coord)) in nodes.iter().enumerate() {
+            if !indexed.insert(id) {
+                continue;
+            }
+            let stop = match nodes.get(i + 1) {
+                Some(next) => next.1,
+                None => coord + graph.node(id).map(|t| t.length()).unwrap_or(0),
             };
+            let reg = Region {
+                path: chr.to_string(),
+                start: coord,
+                stop: stop,
+            };
+            if let Err(err) = index.put(id, &reg) {
+                debug!("{:?} at {}", err, id)
+            }
         }
+    }
+}
+
+// Sequence names of a fasta index.
+fn fai_names(fasta: &String) -> Vec<String> {
+    let file = match File::open(format!("{}.fai", fasta)) {
+        Ok(f) => f,
+        Err(_) => return vec![],
     };
+    BufReader::new(file)
+        .lines()
+        .filter_map(|t| t.ok())
+        .filter_map(|t| t.split("\t").next().map(|t| t.to_string()))
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+// Chromosomes of the reference in `reference.chroms`, with the prefix of the dataset.
+fn chroms_names(config: &Config, data: &ConfigData) -> Vec<String> {
+    let mut s = String::new();
+    let file = File::open(&config.reference.chroms).and_then(|mut t| t.read_to_string(&mut s));
+    if file.is_err() {
+        return vec![];
+    }
+    let chroms: serde_json::Value = match serde_json::from_str(&s) {
+        Ok(chroms) => chroms,
+        Err(_) => return vec![],
+    };
+    match chroms[&data.ref_id].as_array() {
+        Some(items) => items
+            .iter()
+            .filter_map(|t| t["id"].as_str())
+            .map(|t| data.chr_prefix.clone() + t.trim_start_matches("chr"))
+            .collect(),
+        None => vec![],
+    }
+}
+
+// Path names of the dataset, from the graph itself, the `.fai` of `source.reference`,
+// or `reference.chroms`, in this order.
+fn discover_paths(graph: &GraphDB, config: &Config, data: &ConfigData) -> Vec<String> {
+    match graph.as_graph().path_names(config, data) {
+        Ok(ref names) if !names.is_empty() => return names.clone(),
+        Ok(_) => {}
+        Err(e) => debug!("could not list paths of {}: {}", data.name, e),
+    }
+    if let Some(ref fasta) = data.source.reference {
+        let names = fai_names(fasta);
+        if !names.is_empty() {
+            return names;
+        }
+    }
+    chroms_names(config, data)
+}
+
+// Every path is indexed by coordinates, while each node keeps the region of the first path
+// with the prefix it is on, so that haplotypes do not replace reference coordinates.
+fn put_node(
+    index: &NodeIndexWriter,
+    indexed: &mut HashSet<u64>,
+    reference: bool,
+    node_id: u64,
+    reg: &Region,
+) {
+    let result = match reference && indexed.insert(node_id) {
+        true => index.put(node_id, reg),
+        false => index.put_coordinate(node_id, reg),
+    };
+    if let Err(err) = result {
+        debug!("{:?} at {}", err, node_id)
+    }
+}
+
+fn build_node_index(data: &ConfigData, db_name: &String, paths: &Vec<String>) {
+    if let Ok(index) = NodeIndexWriter::create(db_name) {
+        let mut indexed: HashSet<u64> = HashSet::new();
+        'iter: for chr_name in paths.iter() {
+            if let Some(ref path) = data.source.node_index {
+                // Paths without the prefix, such as haplotypes, are stored as they are.
+                let reference = chr_name.starts_with(&data.chr_prefix);
+                let chr = match reference {
+                    true => &chr_name[data.chr_prefix.len()..],
+                    false => chr_name.as_str(),
+                };
+                let path_string = path.clone().replace("{}", chr_name);
+                let path = Path::new(&path_string);
+                debug!("Chromosome:  {:?}, {:?}", chr, path);
+
+                let file = match File::open(path) {
+                    Ok(f) => f,
+                    Err(e) => {
+                        debug!("could not open {}; skipping.", e.description());
+                        continue 'iter;
+                    }
+                };
+                /*
+                let file_gz = match extract_file(path) {
+                    Ok(f) => f,
+                    Err(e) => {continue 'iter;}
+                };
+                */
+
+                let br = BufReader::new(file);
+                let mut last_node: Option<NodeId> = None;
+                for line in br.lines() {
+                    match line {
+                        Ok(l) => {
+                            let items: Vec<u64> =
+                                match l.split("\t").map(|a| a.parse::<u64>()).collect() {
+                                    Ok(items) => items,
+                                    Err(e) => {
+                                        debug!("ignoring a line {:?}: {}", l, e);
+                                        continue;
+                                    }
+                                };
+                            if items.len() > 1 {
+                                if let Some(item) = last_node {
+                                    let reg = Region {
+                                        path: chr.to_string(),
+                                        start: item.coord,
+                                        stop: items[1],
+                                    };
+                                    put_node(&index, &mut indexed, reference, item.id, &reg);
+                                }
+                                last_node = Some(NodeId {
+                                    id: items[0],
+                                    coord: items[1],
+                                });
+                            } else {
+                                continue;
+                            }
+                        }
+                        Err(e) => {
+                            debug!("ignoring error {}", e);
+                            continue;
+                        }
+                    };
+                }
+                if let Some(item) = last_node {
+                    // coord.insert(item.id, Region{ path: (*chr).to_string(), start: item.coord, stop: item.coord + 1000 }); //Todo seems to wrong code.
+                    let reg = Region {
+                        path: chr.to_string(),
+                        start: item.coord,
+                        stop: item.coord + 1000,
+                    };
+                    put_node(&index, &mut indexed, reference, item.id, &reg);
+                }
+            }
+        }
+    }
 }
 
 // It includes only "gene" row.
@@ -297,7 +423,13 @@ fn tmp_new_internal(
     let mut index: u64 = 0;
 
     for record in reader.records() {
-        let rec = record.ok().expect("Error reading record.");
+        let rec = match record {
+            Ok(rec) => rec,
+            Err(e) => {
+                debug!("ignoring a record: {}", e);
+                continue;
+            }
+        };
         let nodes = record_to_nodes(rec, &hashmap, index, &feature.chr_prefix);
         for (key, value) in nodes.into_iter() {
             features.entry(key).or_insert(Vec::new()).push(value);
@@ -321,3 +453,18 @@ fn decode_reader(string: &String) -> io::Result<String> {
     Ok(s)
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fai_names_works() {
+        let names = fai_names(&"test/graph/tiny.fa".to_string());
+        assert_eq!(names, vec!["chr1", "chrM"]);
+        assert_eq!(
+            fai_names(&"test/graph/none.fa".to_string()),
+            Vec::<String>::new()
+        );
+    }
+}
@@ -0,0 +1,420 @@
+use crate::graph::{
+    extract_subgraph, graph_file, node_graph_file, summarize_subgraph, write_subgraph, Edge, Edit,
+    GraphCache, Mapping, MemoryGraph, Node, Path, Position, VgGraph,
+};
+use crate::lib::{Config, ConfigData, Database, OptionalRegion};
+use crate::sankey::write_sankey;
+use crate::summary::Summary;
+use crate::vg::Graph;
+use flate2::read::MultiGzDecoder;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::path::Path as FilePath;
+use std::sync::Arc;
+
+fn invalid(line: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Invalid GFA line: {}", line),
+    )
+}
+
+// Segment names are kept as node ids if numeric, otherwise numbered after the largest one.
+struct Segments {
+    ids: HashMap<String, u64>,
+    lengths: HashMap<u64, u64>,
+}
+
+impl Segments {
+    fn new(segments: &Vec<(String, String)>) -> Segments {
+        let mut ids = HashMap::new();
+        let mut next = segments
+            .iter()
+            .filter_map(|t| t.0.parse::<u64>().ok())
+            .max()
+            .unwrap_or(0)
+            + 1;
+        for &(ref name, _) in segments.iter() {
+            let id = match name.parse::<u64>() {
+                Ok(id) => id,
+                Err(_) => {
+                    next += 1;
+                    next - 1
+                }
+            };
+            ids.insert(name.clone(), id);
+        }
+        let lengths = segments
+            .iter()
+            .map(|t| (ids[&t.0], t.1.len() as u64))
+            .collect();
+        Segments {
+            ids: ids,
+            lengths: lengths,
+        }
+    }
+
+    fn id(&self, name: &str, line: &str) -> Result<u64, Error> {
+        self.ids.get(name).cloned().ok_or_else(|| invalid(line))
+    }
+
+    fn mapping(
+        &self,
+        name: &str,
+        is_reverse: bool,
+        rank: usize,
+        line: &str,
+    ) -> Result<Mapping, Error> {
+        let id = self.id(name, line)?;
+        let length = self.lengths.get(&id).cloned().unwrap_or(0);
+        Ok(Mapping {
+            position: Position {
+                node_id: id,
+                is_reverse: is_reverse,
+                offset: None,
+                coordinate: None,
+            },
+            edit: vec![Edit {
+                from_length: Some(length),
+                to_length: Some(length),
+                sequence: None,
+            }],
+            rank: Some(rank as u64),
+        })
+    }
+}
+
+// Parse S, L, P and W lines of GFA 1.0/1.1 into the same structure as `vg view -j`.
+pub fn parse_gfa<R: Read>(reader: R) -> Result<VgGraph, Error> {
+    let mut segments: Vec<(String, String)> = vec![];
+    let mut lines: Vec<String> = vec![];
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        if line.starts_with("S\t") {
+            let columns: Vec<&str> = line.split('\t').collect();
+            if columns.len() < 3 {
+                return Err(invalid(&line));
+            }
+            segments.push((columns[1].to_string(), columns[2].to_string()));
+        } else if line.starts_with("L\t") || line.starts_with("P\t") || line.starts_with("W\t") {
+            lines.push(line);
+        }
+    }
+    let ids = Segments::new(&segments);
+    let mut graph = VgGraph::default();
+    graph.node = segments
+        .into_iter()
+        .map(|(name, sequence)| Node {
+            id: ids.ids[&name],
+            sequence: sequence,
+            length: None,
+        })
+        .collect();
+
+    for line in lines.iter() {
+        let columns: Vec<&str> = line.split('\t').collect();
+        match columns[0] {
+            "L" if columns.len() >= 5 => graph.edge.push(Edge {
+                from: ids.id(columns[1], line)?,
+                to: ids.id(columns[3], line)?,
+                from_start: columns[2] == "-",
+                to_end: columns[4] == "-",
+            }),
+            "P" if columns.len() >= 3 => {
+                let mut mapping = vec![];
+                for (i, step) in columns[2].split(',').filter(|t| !t.is_empty()).enumerate() {
+                    let (name, orientation) = step.split_at(step.len() - 1);
+                    mapping.push(ids.mapping(name, orientation == "-", i + 1, line)?);
+                }
+                graph.path.push(Path {
+                    name: columns[1].to_string(),
+                    mapping: mapping,
+                    index_of_first_base: None,
+                });
+            }
+            "W" if columns.len() >= 7 => {
+                let mut mapping = vec![];
+                let walk = columns[6];
+                let mut starts: Vec<usize> = walk
+                    .match_indices(|c: char| c == '>' || c == '<')
+                    .map(|t| t.0)
+                    .collect();
+                starts.push(walk.len());
+                for (i, range) in starts.windows(2).enumerate() {
+                    let step = &walk[range[0]..range[1]];
+                    mapping.push(ids.mapping(&step[1..], step.starts_with('<'), i + 1, line)?);
+                }
+                // PanSN naming, with the subrange when the walk does not start at the beginning.
+                let name = match columns[4] {
+                    "0" | "*" => format!("{}#{}#{}", columns[1], columns[2], columns[3]),
+                    start => format!(
+                        "{}#{}#{}[{}-{}]",
+                        columns[1], columns[2], columns[3], start, columns[5]
+                    ),
+                };
+                graph.path.push(Path {
+                    name: name,
+                    mapping: mapping,
+                    index_of_first_base: None,
+                });
+            }
+            _ => return Err(invalid(line)),
+        }
+    }
+    Ok(graph)
+}
+
+fn orientation(is_reverse: bool) -> &'static str {
+    match is_reverse {
+        true => "-",
+        false => "+",
+    }
+}
+
+// The name of a piece of a path, `name[start-end]` as W lines are named, where the range is on the path,
+// or of the ranks when the coordinates are unknown.
+pub fn sub_path_name(name: &str, mappings: &[Mapping], nodes: &HashMap<u64, &Node>) -> String {
+    let (start, end) = match mappings[0].position.coordinate {
+        Some(start) => {
+            let length: u64 = mappings
+                .iter()
+                .map(|t| t.length(nodes.get(&t.position.node_id).cloned()))
+                .sum();
+            (start, start + length)
+        }
+        None => (
+            mappings[0].rank.unwrap_or(1).saturating_sub(1),
+            mappings[mappings.len() - 1].rank.unwrap_or(0),
+        ),
+    };
+    format!("{}[{}-{}]", name, start, end)
+}
+
+// Write the graph as GFA 1.1, with a P-line for each path, or for each piece of a path
+// which leaves the region and comes back.
+pub fn write_gfa<W: Write>(writer: W, graph: &VgGraph) -> Result<(), Error> {
+    let mut writer = BufWriter::new(writer);
+    writeln!(writer, "H\tVN:Z:1.1")?;
+    for node in graph.node.iter() {
+        writeln!(writer, "S\t{}\t{}", node.id, node.sequence)?;
+    }
+    for edge in graph.edge.iter() {
+        writeln!(
+            writer,
+            "L\t{}\t{}\t{}\t{}\t0M",
+            edge.from,
+            orientation(edge.from_start),
+            edge.to,
+            orientation(edge.to_end)
+        )?;
+    }
+    let nodes: HashMap<u64, &Node> = graph.node.iter().map(|t| (t.id, t)).collect();
+    for path in graph.path.iter() {
+        // A path leaving the region is written as sub-paths, since steps of a P line must be linked.
+        let fragments = path.fragments();
+        for mappings in fragments.iter() {
+            let name = match fragments.len() {
+                1 => path.name.clone(),
+                _ => sub_path_name(&path.name, mappings, &nodes),
+            };
+            let steps: Vec<String> = mappings
+                .iter()
+                .map(|t| {
+                    format!(
+                        "{}{}",
+                        t.position.node_id,
+                        orientation(t.position.is_reverse)
+                    )
+                })
+                .collect();
+            writeln!(writer, "P\t{}\t{}\t*", name, steps.join(","))?;
+        }
+    }
+    writer.flush()
+}
+
+pub fn load_gfa(path: &FilePath) -> Result<MemoryGraph, Error> {
+    let file = File::open(path)?;
+    let graph = match path.extension().and_then(|t| t.to_str()) {
+        Some("gz") => parse_gfa(MultiGzDecoder::new(file))?,
+        _ => parse_gfa(file)?,
+    };
+    Ok(MemoryGraph::new(graph))
+}
+
+// The Graph implementation on GFA files given as `source.gfa`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Gfa {
+    #[serde(skip)]
+    cache: GraphCache,
+}
+
+impl PartialEq for Gfa {
+    fn eq(&self, _: &Gfa) -> bool {
+        true
+    }
+}
+
+impl Gfa {
+    pub fn new() -> Gfa {
+        Gfa::default()
+    }
+
+    pub fn load(&self, filename: &String) -> Result<Arc<MemoryGraph>, Error> {
+        self.cache.load(filename, load_gfa)
+    }
+}
+
+impl Graph for Gfa {
+    fn version(&self, _config: &Config) -> i32 {
+        -1
+    }
+    fn nodes_list(&self) {
+        return ();
+    }
+    fn node_sequence(
+        &self,
+        _config: &Config,
+        data: &ConfigData,
+        database: &Database,
+        node_id: u64,
+    ) -> Result<Option<String>, Error> {
+        let file = match data.source.gfa {
+            Some(ref file) => node_graph_file(file, data, database, node_id),
+            None => None,
+        };
+        match file {
+            Some(file) => Ok(self.load(&file)?.node(node_id).map(|t| t.sequence.clone())),
+            None => Ok(None),
+        }
+    }
+    fn path_names(&self, _config: &Config, data: &ConfigData) -> Result<Vec<String>, Error> {
+        match data.source.gfa {
+            Some(ref file) if !file.contains("{}") => {
+                Ok(self.load(file)?.path_names().into_iter().cloned().collect())
+            }
+            _ => Ok(vec![]),
+        }
+    }
+    fn generate_graph_to_file_wo_helper(
+        &self,
+        path: OptionalRegion,
+        _data: i64,
+        file: &FilePath,
+        steps: &Option<i64>,
+        _config: &Config,
+        data: &ConfigData,
+        _database: &Database,
+        xgfile: &String,
+        tmp: bool,
+        max_interval: u64,
+        downsample: bool,
+        gam: bool,
+        _version: i32,
+        _threads: i32,
+    ) -> Result<bool, Error> {
+        if gam {
+            debug!("Alignments are not supported on GFA; ignored.");
+        }
+        let graph = self.load(&graph_file(&path, &data.source.gfa, xgfile, tmp))?;
+        let subgraph = extract_subgraph(&graph, &path, steps, data, max_interval, downsample)?;
+        write_subgraph(file, &subgraph).map(|_| true)
+    }
+
+    fn generate_graph_to_file(
+        &self,
+        path: OptionalRegion,
+        _data: i64,
+        file: &File,
+        steps: &Option<i64>,
+        _config: &Config,
+        data: &ConfigData,
+        _database: &Database,
+        xgfile: &String,
+        tmp: bool,
+        max_interval: u64,
+        downsample: bool,
+    ) -> Result<bool, Error> {
+        let graph = self.load(&graph_file(&path, &data.source.gfa, xgfile, tmp))?;
+        let subgraph = extract_subgraph(&graph, &path, steps, data, max_interval, downsample)?;
+        write_sankey(file, &subgraph, &data.chr_prefix, &HashMap::new())
+    }
+
+    fn generate_summary(
+        &self,
+        path: &OptionalRegion,
+        steps: &Option<i64>,
+        _config: &Config,
+        data: &ConfigData,
+        _database: &Database,
+        max_interval: u64,
+        resolution: u64,
+    ) -> Result<Summary, Error> {
+        let graph = self.load(&graph_file(path, &data.source.gfa, &data.source.xg, false))?;
+        summarize_subgraph(&graph, path, steps, max_interval, resolution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tiny() -> VgGraph {
+        parse_gfa(File::open("test/graph/tiny.gfa").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parse_gfa_works() {
+        let graph = tiny();
+        let ids: Vec<u64> = graph.node.iter().map(|t| t.id).collect();
+        assert_eq!(ids, vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(graph.edge.len(), 6);
+        assert_eq!(graph.edge[5].to_end, true);
+        let names: Vec<&str> = graph.path.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["chr1", "HG002#1#chr1", "HG002#2#chr1[4-10]"]);
+        let walk: Vec<(u64, bool)> = graph.path[1]
+            .mapping
+            .iter()
+            .map(|t| (t.position.node_id, t.position.is_reverse))
+            .collect();
+        assert_eq!(walk, vec![(1, false), (3, false), (4, false), (5, false)]);
+        assert_eq!(graph.path[2].mapping[1].position.node_id, 6);
+        assert_eq!(graph.path[2].mapping[1].position.is_reverse, true);
+    }
+
+    #[test]
+    fn gfa_subgraph_works() {
+        let graph = MemoryGraph::new(tiny());
+        let region = OptionalRegion::new("chr1:5-9".to_string()).unwrap();
+        let subgraph = graph.subgraph(&region, 1).unwrap();
+        let ids: Vec<u64> = subgraph.node.iter().map(|t| t.id).collect();
+        assert_eq!(ids, vec![2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn write_gfa_works() {
+        let graph = tiny();
+        let mut buf = vec![];
+        write_gfa(&mut buf, &graph).unwrap();
+        let gfa = String::from_utf8(buf.clone()).unwrap();
+        let lines: Vec<&str> = gfa.lines().collect();
+        assert_eq!(lines[0], "H\tVN:Z:1.1");
+        assert_eq!(lines[1], "S\t1\tACGT");
+        assert!(lines.contains(&"L\t5\t+\t6\t-\t0M"));
+        assert!(lines.contains(&"P\tchr1\t1+,2+,4+,5+\t*"));
+        assert!(lines.contains(&"P\tHG002#2#chr1[4-10]\t4+,6-\t*"));
+        assert_eq!(parse_gfa(&buf[..]).unwrap(), graph);
+
+        // chr1 leaves the region at node 2 and comes back at node 4.
+        let region = OptionalRegion::new("chr1:0-10".to_string()).unwrap();
+        let mut subgraph = MemoryGraph::new(tiny()).subgraph(&region, 0).unwrap();
+        subgraph.path[0].mapping.remove(1);
+        let mut buf = vec![];
+        write_gfa(&mut buf, &subgraph).unwrap();
+        let gfa = String::from_utf8(buf).unwrap();
+        let lines: Vec<&str> = gfa.lines().collect();
+        assert!(lines.contains(&"P\tchr1[0-4]\t1+\t*"));
+        assert!(lines.contains(&"P\tchr1[5-10]\t4+,5+\t*"));
+    }
+}
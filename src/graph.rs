@@ -0,0 +1,885 @@
+use crate::lib::{Config, ConfigData, Database, OptionalRegion, Region};
+use crate::sankey::write_sankey;
+use crate::summary::{summarize, Summary};
+use crate::vg::{
+    check_interval, Graph, DEFAULT_STEP, DOWNSAMPLE_NODES, DOWNSAMPLE_SEQUENCE, MAX_STEP,
+};
+use serde::de::{self, Deserialize, Deserializer};
+use serde_json;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read};
+use std::path::Path as FilePath;
+use std::sync::{Arc, RwLock};
+
+// vg emits 64-bit integers either as numbers or as strings depending on its version.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Id {
+    Number(u64),
+    String(String),
+}
+
+fn deserialize_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    match Id::deserialize(deserializer)? {
+        Id::Number(n) => Ok(n),
+        Id::String(s) => s.parse::<u64>().map_err(de::Error::custom),
+    }
+}
+
+fn deserialize_opt_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    match Option::<Id>::deserialize(deserializer)? {
+        Some(Id::Number(n)) => Ok(Some(n)),
+        Some(Id::String(s)) => s.parse::<u64>().map(Some).map_err(de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+// Serde types for the output of `vg view -j`.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct VgGraph {
+    #[serde(default)]
+    pub node: Vec<Node>,
+    #[serde(default)]
+    pub edge: Vec<Edge>,
+    #[serde(default)]
+    pub path: Vec<Path>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Node {
+    #[serde(deserialize_with = "deserialize_id")]
+    pub id: u64,
+    #[serde(default)]
+    pub sequence: String,
+    // The bases a merged node of a downsampled graph spans, as its sequence is truncated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<u64>,
+}
+
+impl Node {
+    pub fn length(&self) -> u64 {
+        self.length.unwrap_or(self.sequence.len() as u64)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Edge {
+    #[serde(deserialize_with = "deserialize_id")]
+    pub from: u64,
+    #[serde(deserialize_with = "deserialize_id")]
+    pub to: u64,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub from_start: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub to_end: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Path {
+    pub name: String,
+    #[serde(default)]
+    pub mapping: Vec<Mapping>,
+    #[serde(
+        rename = "indexOfFirstBase",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub index_of_first_base: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mapping {
+    pub position: Position,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub edit: Vec<Edit>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_opt_id",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub rank: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+    #[serde(deserialize_with = "deserialize_id")]
+    pub node_id: u64,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub is_reverse: bool,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_opt_id",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub offset: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coordinate: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Edit {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from_length: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to_length: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<String>,
+}
+
+impl VgGraph {
+    // Merge every JSON object of the stream, as `vg view -j` may emit several.
+    pub fn from_reader<R: Read>(reader: R) -> Result<VgGraph, Error> {
+        let mut graph = VgGraph::default();
+        for chunk in serde_json::Deserializer::from_reader(reader).into_iter::<VgGraph>() {
+            let chunk = chunk.map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+            graph.node.extend(chunk.node);
+            graph.edge.extend(chunk.edge);
+            graph.path.extend(chunk.path);
+        }
+        Ok(graph)
+    }
+
+    // Give each mapping its offset on the path, where it is not known yet.
+    pub fn fill_coordinates(&mut self) {
+        let lengths: HashMap<u64, u64> = self.node.iter().map(|t| (t.id, t.length())).collect();
+        for path in self.path.iter_mut() {
+            let mut offset = 0;
+            for mapping in path.mapping.iter_mut() {
+                let length = match mapping.edit.is_empty() {
+                    true => lengths.get(&mapping.position.node_id).cloned().unwrap_or(0),
+                    false => mapping
+                        .edit
+                        .iter()
+                        .map(|t| t.from_length.unwrap_or(0))
+                        .sum(),
+                };
+                mapping.position.coordinate = mapping.position.coordinate.or(Some(offset));
+                offset += length;
+            }
+        }
+    }
+}
+
+impl Mapping {
+    // The number of bases of the node this mapping covers.
+    pub fn length(&self, node: Option<&Node>) -> u64 {
+        if self.edit.is_empty() {
+            node.map(|t| t.length()).unwrap_or(0)
+        } else {
+            self.edit.iter().map(|t| t.from_length.unwrap_or(0)).sum()
+        }
+    }
+}
+
+impl Path {
+    // Mappings split where the ranks skip, as the path may leave the region and come back.
+    pub fn fragments(&self) -> Vec<&[Mapping]> {
+        let mut fragments = vec![];
+        let mut start = 0;
+        for i in 1..self.mapping.len() {
+            match (self.mapping[i - 1].rank, self.mapping[i].rank) {
+                (Some(a), Some(b)) if a + 1 != b => {
+                    fragments.push(&self.mapping[start..i]);
+                    start = i;
+                }
+                _ => {}
+            }
+        }
+        if start < self.mapping.len() {
+            fragments.push(&self.mapping[start..]);
+        }
+        fragments
+    }
+}
+
+// A whole graph held in memory and indexed for region queries.
+#[derive(Debug, Default)]
+pub struct MemoryGraph {
+    nodes: HashMap<u64, Node>,
+    edges: Vec<Edge>,
+    adjacency: HashMap<u64, Vec<usize>>, // Node id to indices of edges.
+    paths: Vec<Path>,
+    offsets: Vec<Vec<u64>>, // The offset of each mapping on its path.
+    path_index: HashMap<String, usize>,
+    node_paths: HashMap<u64, Vec<(usize, usize)>>, // Node id to (path, mapping) indices.
+}
+
+impl MemoryGraph {
+    pub fn new(graph: VgGraph) -> MemoryGraph {
+        let mut memory = MemoryGraph::default();
+        memory.extend(graph);
+        memory.index();
+        memory
+    }
+
+    // `vg view -j` emits one JSON object per chunk, so all of them are merged.
+    pub fn from_vg_json(path: &FilePath) -> Result<MemoryGraph, Error> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut memory = MemoryGraph::default();
+        for chunk in serde_json::Deserializer::from_reader(reader).into_iter::<VgGraph>() {
+            memory.extend(chunk.map_err(|e| Error::new(ErrorKind::InvalidData, e))?);
+        }
+        memory.index();
+        Ok(memory)
+    }
+
+    fn extend(&mut self, graph: VgGraph) {
+        for node in graph.node.into_iter() {
+            self.nodes.insert(node.id, node);
+        }
+        self.edges.extend(graph.edge);
+        for path in graph.path.into_iter() {
+            match self.path_index.get(&path.name) {
+                Some(&index) => self.paths[index].mapping.extend(path.mapping),
+                None => {
+                    self.path_index.insert(path.name.clone(), self.paths.len());
+                    self.paths.push(path);
+                }
+            }
+        }
+    }
+
+    fn index(&mut self) {
+        self.adjacency.clear();
+        for (i, edge) in self.edges.iter().enumerate() {
+            self.adjacency.entry(edge.from).or_insert(vec![]).push(i);
+            if edge.from != edge.to {
+                self.adjacency.entry(edge.to).or_insert(vec![]).push(i);
+            }
+        }
+        self.offsets.clear();
+        self.node_paths.clear();
+        for (i, path) in self.paths.iter_mut().enumerate() {
+            path.mapping.sort_by_key(|t| t.rank.unwrap_or(0));
+            let mut offsets = Vec::with_capacity(path.mapping.len());
+            let mut offset = 0;
+            for (j, mapping) in path.mapping.iter().enumerate() {
+                offsets.push(offset);
+                offset += mapping.length(self.nodes.get(&mapping.position.node_id));
+                self.node_paths
+                    .entry(mapping.position.node_id)
+                    .or_insert(vec![])
+                    .push((i, j));
+            }
+            self.offsets.push(offsets);
+        }
+    }
+
+    pub fn node(&self, id: u64) -> Option<&Node> {
+        self.nodes.get(&id)
+    }
+
+    pub fn path_names(&self) -> Vec<&String> {
+        self.paths.iter().map(|t| &t.name).collect()
+    }
+
+    // Node ids along the path with their offsets on it.
+    pub fn path_nodes(&self, name: &str) -> Vec<(u64, u64)> {
+        match self.path_index.get(name) {
+            Some(&i) => self.paths[i]
+                .mapping
+                .iter()
+                .zip(self.offsets[i].iter())
+                .map(|(mapping, &offset)| (mapping.position.node_id, offset))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    // Nodes on the path overlapping the region.
+    fn seed_nodes(&self, region: &OptionalRegion) -> Result<Vec<u64>, Error> {
+        let index = *self.path_index.get(&region.path).ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("Path {} is not found", region.path),
+            )
+        })?;
+        let path = &self.paths[index];
+        let offsets = &self.offsets[index];
+        let start = region.start.unwrap_or(0);
+        let stop = region.stop.unwrap_or(start + 1).max(start + 1);
+        let first = match offsets.binary_search(&start) {
+            Ok(x) => x,
+            Err(x) => x.saturating_sub(1),
+        };
+        Ok((first..offsets.len())
+            .take_while(|&j| offsets[j] < stop)
+            .map(|j| path.mapping[j].position.node_id)
+            .collect())
+    }
+
+    // Extract the region with its neighbourhood of `steps` edges, as `vg find -p -c` does.
+    pub fn subgraph(&self, region: &OptionalRegion, steps: i64) -> Result<VgGraph, Error> {
+        let mut visited: BTreeSet<u64> = BTreeSet::new();
+        let mut queue: VecDeque<(u64, i64)> = VecDeque::new();
+        for id in self.seed_nodes(region)? {
+            if visited.insert(id) {
+                queue.push_back((id, 0));
+            }
+        }
+        while let Some((id, depth)) = queue.pop_front() {
+            if depth >= steps {
+                continue;
+            }
+            for &i in self.adjacency.get(&id).map(|t| t.as_slice()).unwrap_or(&[]) {
+                let edge = &self.edges[i];
+                let next = if edge.from == id { edge.to } else { edge.from };
+                if visited.insert(next) {
+                    queue.push_back((next, depth + 1));
+                }
+            }
+        }
+
+        let node = visited
+            .iter()
+            .filter_map(|id| self.nodes.get(id).cloned())
+            .collect();
+        let edge = self
+            .edges
+            .iter()
+            .filter(|t| visited.contains(&t.from) && visited.contains(&t.to))
+            .cloned()
+            .collect();
+        let mut mappings: HashMap<usize, Vec<usize>> = HashMap::new();
+        for id in visited.iter() {
+            for &(i, j) in self.node_paths.get(id).map(|t| t.as_slice()).unwrap_or(&[]) {
+                mappings.entry(i).or_insert(vec![]).push(j);
+            }
+        }
+        let mut path_indices: Vec<usize> = mappings.keys().cloned().collect();
+        path_indices.sort();
+        let path = path_indices
+            .into_iter()
+            .map(|i| {
+                let mut indices = mappings.remove(&i).unwrap_or(vec![]);
+                indices.sort();
+                Path {
+                    name: self.paths[i].name.clone(),
+                    mapping: indices
+                        .into_iter()
+                        .map(|j| {
+                            let mut mapping = self.paths[i].mapping[j].clone();
+                            mapping.position.coordinate = Some(self.offsets[i][j]);
+                            mapping
+                        })
+                        .collect(),
+                    index_of_first_base: None,
+                }
+            })
+            .collect();
+        Ok(VgGraph {
+            node: node,
+            edge: edge,
+            path: path,
+        })
+    }
+}
+
+// Keep coordinates only on reference paths, as `proto/graph-helper2.rb` does.
+pub fn add_coordinate(graph: &mut VgGraph, chr_prefix: &str) {
+    for path in graph.path.iter_mut() {
+        if path.name.len() >= 24 || !path.name.starts_with(chr_prefix) {
+            for mapping in path.mapping.iter_mut() {
+                mapping.position.coordinate = None;
+            }
+            continue;
+        }
+        path.index_of_first_base = path.mapping.first().and_then(|t| t.position.coordinate);
+    }
+}
+
+// Set coordinates found by `vg find -P` or the node index on the mappings of the same path.
+pub fn set_coordinates(graph: &mut VgGraph, regions: &HashMap<u64, Region>, chr_prefix: &str) {
+    for path in graph.path.iter_mut() {
+        for mapping in path.mapping.iter_mut() {
+            if let Some(region) = regions.get(&mapping.position.node_id) {
+                if region.path == path.name
+                    || region.path == path.name.trim_start_matches(chr_prefix)
+                {
+                    mapping.position.coordinate = Some(region.start);
+                }
+            }
+        }
+    }
+}
+
+// An overview of a region longer than the max interval: consecutive nodes on `path` are merged
+// into at most about `nodes` nodes of similar length, each of which keeps the id of its first node
+// and only the first `DOWNSAMPLE_SEQUENCE` bases of its members.
+// Nodes off `path` are dropped, and other paths are kept through the merged nodes.
+pub fn downsample_graph(graph: &VgGraph, path: &str, nodes: u64) -> VgGraph {
+    let sequences: HashMap<u64, &Node> = graph.node.iter().map(|t| (t.id, t)).collect();
+    let reference = match graph.path.iter().find(|t| t.name == path) {
+        Some(reference) => reference,
+        None => return VgGraph::default(),
+    };
+    let length: u64 = reference
+        .mapping
+        .iter()
+        .map(|t| t.length(sequences.get(&t.position.node_id).cloned()))
+        .sum();
+    let bin_length = (length / nodes.max(1)).max(1);
+
+    let mut merged: Vec<Node> = vec![];
+    let mut merged_of: HashMap<u64, u64> = HashMap::new(); // Node id to its merged node id.
+    for mapping in reference.mapping.iter() {
+        let id = mapping.position.node_id;
+        if merged_of.contains_key(&id) {
+            continue;
+        }
+        let node = match sequences.get(&id) {
+            Some(node) => node,
+            None => continue,
+        };
+        let full = merged
+            .last()
+            .map(|t| t.length() >= bin_length)
+            .unwrap_or(true);
+        if full {
+            merged.push(Node {
+                id: id,
+                sequence: String::new(),
+                length: Some(0),
+            });
+        }
+        let last = merged.last_mut().unwrap();
+        let rest = DOWNSAMPLE_SEQUENCE.saturating_sub(last.sequence.len());
+        last.sequence.extend(node.sequence.chars().take(rest));
+        last.length = Some(last.length() + node.length());
+        merged_of.insert(id, last.id);
+    }
+
+    let mut seen = HashSet::new();
+    let edge = graph
+        .edge
+        .iter()
+        .filter_map(|t| {
+            let from = *merged_of.get(&t.from)?;
+            let to = *merged_of.get(&t.to)?;
+            if from == to || !seen.insert((from, to, t.from_start, t.to_end)) {
+                return None;
+            }
+            Some(Edge {
+                from: from,
+                to: to,
+                from_start: t.from_start,
+                to_end: t.to_end,
+            })
+        })
+        .collect();
+    let path = graph
+        .path
+        .iter()
+        .filter_map(|t| {
+            let mut mapping: Vec<Mapping> = vec![];
+            for m in t.mapping.iter() {
+                let id = match merged_of.get(&m.position.node_id) {
+                    Some(&id) => id,
+                    None => continue,
+                };
+                if mapping.last().map(|t| t.position.node_id) == Some(id) {
+                    continue;
+                }
+                mapping.push(Mapping {
+                    position: Position {
+                        node_id: id,
+                        is_reverse: m.position.is_reverse,
+                        offset: None,
+                        coordinate: m.position.coordinate,
+                    },
+                    edit: vec![],
+                    rank: Some(mapping.len() as u64 + 1),
+                });
+            }
+            match mapping.is_empty() {
+                true => None,
+                false => Some(Path {
+                    name: t.name.clone(),
+                    mapping: mapping,
+                    index_of_first_base: None,
+                }),
+            }
+        })
+        .collect();
+    VgGraph {
+        node: merged,
+        edge: edge,
+        path: path,
+    }
+}
+
+// The outputs of `/graph`, chosen by `raw` and `format`.
+#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+pub enum GraphFormat {
+    Sankey,
+    Raw, // vg JSON with coordinates.
+    Gfa,
+    Svg,   // Rendered by `bin.graphviz`.
+    Fasta, // Sequences of the paths.
+}
+
+impl GraphFormat {
+    pub fn all() -> [GraphFormat; 5] {
+        [
+            GraphFormat::Sankey,
+            GraphFormat::Raw,
+            GraphFormat::Gfa,
+            GraphFormat::Svg,
+            GraphFormat::Fasta,
+        ]
+    }
+
+    pub fn new(raw: bool, format: Option<&str>) -> Result<GraphFormat, String> {
+        match format {
+            None | Some("json") => match raw {
+                true => Ok(GraphFormat::Raw),
+                false => Ok(GraphFormat::Sankey),
+            },
+            Some("gfa") => Ok(GraphFormat::Gfa),
+            Some("svg") => Ok(GraphFormat::Svg),
+            Some("fasta") => Ok(GraphFormat::Fasta),
+            Some(format) => Err(format!("Unknown format: {}", format)),
+        }
+    }
+
+    // The prefix of cache files, which tells the format at a glance.
+    pub fn prefix(&self) -> &'static str {
+        match *self {
+            GraphFormat::Sankey => "",
+            GraphFormat::Raw => "raw_",
+            GraphFormat::Gfa => "gfa_",
+            GraphFormat::Svg => "svg_",
+            GraphFormat::Fasta => "fasta_",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            GraphFormat::Sankey | GraphFormat::Raw => "json",
+            GraphFormat::Gfa => "gfa",
+            GraphFormat::Svg => "svg",
+            GraphFormat::Fasta => "fa",
+        }
+    }
+}
+
+// Graphs loaded once and kept in memory, keyed by their filename.
+#[derive(Debug, Default)]
+pub struct GraphCache {
+    graphs: RwLock<HashMap<String, Arc<MemoryGraph>>>,
+}
+
+impl GraphCache {
+    pub fn load<F>(&self, filename: &String, loader: F) -> Result<Arc<MemoryGraph>, Error>
+    where
+        F: Fn(&FilePath) -> Result<MemoryGraph, Error>,
+    {
+        if let Ok(graphs) = self.graphs.read() {
+            if let Some(graph) = graphs.get(filename) {
+                return Ok(graph.clone());
+            }
+        }
+        info!("Loading graph: {}", filename);
+        let graph = Arc::new(loader(FilePath::new(filename))?);
+        if let Ok(mut graphs) = self.graphs.write() {
+            graphs.insert(filename.clone(), graph.clone());
+        }
+        Ok(graph)
+    }
+}
+
+// Uploaded graphs are given by the caller, otherwise the graph of the dataset is used.
+pub fn graph_file(
+    region: &OptionalRegion,
+    source: &Option<String>,
+    file: &String,
+    tmp: bool,
+) -> String {
+    let file = match *source {
+        Some(ref source) if !tmp => source,
+        _ => file,
+    };
+    file.replace("{}", &region.path)
+}
+
+// The graph file containing the node; per-path files are found by the node index.
+pub fn node_graph_file(
+    file: &String,
+    data: &ConfigData,
+    database: &Database,
+    node_id: u64,
+) -> Option<String> {
+    if !file.contains("{}") {
+        return Some(file.clone());
+    }
+    let region = database.rocks.get(&data.name)?.get(node_id)?;
+    Some(file.replace("{}", &(data.chr_prefix.clone() + &region.path)))
+}
+
+// Regions longer than the max interval are downsampled if `downsample` is set.
+pub fn extract_subgraph(
+    graph: &MemoryGraph,
+    path: &OptionalRegion,
+    steps: &Option<i64>,
+    data: &ConfigData,
+    max_interval: u64,
+    downsample: bool,
+) -> Result<VgGraph, Error> {
+    let mut steps = steps.unwrap_or(DEFAULT_STEP);
+    if steps > MAX_STEP {
+        steps = MAX_STEP;
+    }
+    if check_interval(path, max_interval, downsample)? {
+        let subgraph = graph.subgraph(path, 0)?;
+        let mut subgraph = downsample_graph(&subgraph, &path.path, DOWNSAMPLE_NODES);
+        add_coordinate(&mut subgraph, &data.chr_prefix);
+        return Ok(subgraph);
+    }
+    let mut subgraph = graph.subgraph(path, steps)?;
+    add_coordinate(&mut subgraph, &data.chr_prefix);
+    Ok(subgraph)
+}
+
+// Regions longer than the max interval are summarized as well, up to the downsampling limit.
+pub fn summarize_subgraph(
+    graph: &MemoryGraph,
+    path: &OptionalRegion,
+    steps: &Option<i64>,
+    max_interval: u64,
+    resolution: u64,
+) -> Result<Summary, Error> {
+    check_interval(path, max_interval, true)?;
+    let steps = steps.unwrap_or(DEFAULT_STEP).min(MAX_STEP);
+    Ok(summarize(
+        &graph.subgraph(path, steps)?,
+        &path.path,
+        resolution,
+    ))
+}
+
+pub fn write_subgraph(file: &FilePath, subgraph: &VgGraph) -> Result<(), Error> {
+    let writer = BufWriter::new(File::create(file)?);
+    serde_json::to_writer(writer, subgraph).map_err(|e| Error::new(ErrorKind::Other, e))
+}
+
+// The Graph implementation without vg, on the output of `vg view -j`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Native {
+    #[serde(skip)]
+    cache: GraphCache,
+}
+
+impl PartialEq for Native {
+    fn eq(&self, _: &Native) -> bool {
+        true
+    }
+}
+
+impl Native {
+    pub fn new() -> Native {
+        Native::default()
+    }
+
+    pub fn load(&self, filename: &String) -> Result<Arc<MemoryGraph>, Error> {
+        self.cache.load(filename, MemoryGraph::from_vg_json)
+    }
+}
+
+impl Graph for Native {
+    fn version(&self, _config: &Config) -> i32 {
+        -1
+    }
+    fn nodes_list(&self) {
+        return ();
+    }
+    fn node_sequence(
+        &self,
+        _config: &Config,
+        data: &ConfigData,
+        database: &Database,
+        node_id: u64,
+    ) -> Result<Option<String>, Error> {
+        let file = match data.source.json {
+            Some(ref file) => node_graph_file(file, data, database, node_id),
+            None => None,
+        };
+        match file {
+            Some(file) => Ok(self.load(&file)?.node(node_id).map(|t| t.sequence.clone())),
+            None => Ok(None),
+        }
+    }
+    fn path_names(&self, _config: &Config, data: &ConfigData) -> Result<Vec<String>, Error> {
+        match data.source.json {
+            Some(ref file) if !file.contains("{}") => {
+                Ok(self.load(file)?.path_names().into_iter().cloned().collect())
+            }
+            _ => Ok(vec![]),
+        }
+    }
+    fn generate_graph_to_file_wo_helper(
+        &self,
+        path: OptionalRegion,
+        _data: i64,
+        file: &FilePath,
+        steps: &Option<i64>,
+        _config: &Config,
+        data: &ConfigData,
+        _database: &Database,
+        xgfile: &String,
+        tmp: bool,
+        max_interval: u64,
+        downsample: bool,
+        gam: bool,
+        _version: i32,
+        _threads: i32,
+    ) -> Result<bool, Error> {
+        if gam {
+            debug!("Alignments are not supported without vg; ignored.");
+        }
+        let graph = self.load(&graph_file(&path, &data.source.json, xgfile, tmp))?;
+        let subgraph = extract_subgraph(&graph, &path, steps, data, max_interval, downsample)?;
+        write_subgraph(file, &subgraph).map(|_| true)
+    }
+
+    fn generate_graph_to_file(
+        &self,
+        path: OptionalRegion,
+        _data: i64,
+        file: &File,
+        steps: &Option<i64>,
+        _config: &Config,
+        data: &ConfigData,
+        _database: &Database,
+        xgfile: &String,
+        tmp: bool,
+        max_interval: u64,
+        downsample: bool,
+    ) -> Result<bool, Error> {
+        let graph = self.load(&graph_file(&path, &data.source.json, xgfile, tmp))?;
+        let subgraph = extract_subgraph(&graph, &path, steps, data, max_interval, downsample)?;
+        write_sankey(file, &subgraph, &data.chr_prefix, &HashMap::new())
+    }
+
+    fn generate_summary(
+        &self,
+        path: &OptionalRegion,
+        steps: &Option<i64>,
+        _config: &Config,
+        data: &ConfigData,
+        _database: &Database,
+        max_interval: u64,
+        resolution: u64,
+    ) -> Result<Summary, Error> {
+        let graph = self.load(&graph_file(path, &data.source.json, &data.source.xg, false))?;
+        summarize_subgraph(&graph, path, steps, max_interval, resolution)
+    }
+}
+
+// chr1:0-10 of test/graph/tiny.json with 2 steps, which tests of other modules share.
+#[cfg(test)]
+pub fn tiny_subgraph() -> VgGraph {
+    let graph = MemoryGraph::from_vg_json(FilePath::new("test/graph/tiny.json")).unwrap();
+    let region = OptionalRegion::new("chr1:0-10".to_string()).unwrap();
+    graph.subgraph(&region, 2).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tiny() -> MemoryGraph {
+        MemoryGraph::from_vg_json(FilePath::new("test/graph/tiny.json")).unwrap()
+    }
+
+    fn region(path: &str) -> OptionalRegion {
+        OptionalRegion::new(path.to_string()).unwrap()
+    }
+
+    fn ids(graph: &VgGraph) -> Vec<u64> {
+        graph.node.iter().map(|t| t.id).collect()
+    }
+
+    #[test]
+    fn load_works() {
+        let graph = tiny();
+        assert_eq!(graph.node(2).map(|t| t.sequence.as_str()), Some("T"));
+        assert_eq!(graph.path_names(), vec!["chr1", "alt1"]);
+        assert_eq!(graph.offsets[0], vec![0, 4, 5, 9]);
+    }
+
+    #[test]
+    fn subgraph_works() {
+        let graph = tiny();
+        assert_eq!(
+            ids(&graph.subgraph(&region("chr1:4-5"), 0).unwrap()),
+            vec![2]
+        );
+        assert_eq!(
+            ids(&graph.subgraph(&region("chr1:4-5"), 1).unwrap()),
+            vec![1, 2, 4]
+        );
+        assert_eq!(
+            ids(&graph.subgraph(&region("chr1:4-5"), 2).unwrap()),
+            vec![1, 2, 3, 4, 5]
+        );
+        assert!(graph.subgraph(&region("chr9:4-5"), 1).is_err());
+    }
+
+    #[test]
+    fn coordinate_works() {
+        let graph = tiny();
+        let mut subgraph = graph.subgraph(&region("chr1:5-9"), 1).unwrap();
+        add_coordinate(&mut subgraph, "chr");
+        let chr1 = &subgraph.path[0];
+        assert_eq!(chr1.index_of_first_base, Some(4));
+        let coordinates: Vec<Option<u64>> =
+            chr1.mapping.iter().map(|t| t.position.coordinate).collect();
+        assert_eq!(coordinates, vec![Some(4), Some(5), Some(9)]);
+        let alt1 = &subgraph.path[1];
+        assert_eq!(alt1.name, "alt1");
+        assert_eq!(alt1.index_of_first_base, None);
+        assert!(alt1.mapping.iter().all(|t| t.position.coordinate.is_none()));
+    }
+
+    #[test]
+    fn downsample_works() {
+        let graph = tiny();
+        assert!(!check_interval(&region("chr1:0-10"), 10, false).unwrap());
+        assert!(check_interval(&region("chr1:0-10"), 4, true).unwrap());
+        let e = check_interval(&region("chr1:0-10"), 4, false).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        assert!(check_interval(&region("chr1:0-10"), 0, true).is_err());
+
+        let subgraph = graph.subgraph(&region("chr1:0-10"), 0).unwrap();
+        let downsampled = downsample_graph(&subgraph, "chr1", 2);
+        assert_eq!(ids(&downsampled), vec![1, 4]);
+        assert_eq!(downsampled.node[0].sequence, "ACGTT");
+        assert_eq!(downsampled.node[1].sequence, "CCCCA");
+        assert_eq!(downsampled.node[1].length, Some(5));
+        // A merged node keeps only the first bases of its members.
+        let overview = downsample_graph(&subgraph, "chr1", 1);
+        assert_eq!(ids(&overview), vec![1]);
+        assert_eq!(overview.node[0].sequence, "ACGTTCCC");
+        assert_eq!(overview.node[0].length(), 10);
+        assert_eq!(
+            overview.path[0].mapping[0].length(Some(&overview.node[0])),
+            10
+        );
+        assert!(overview.edge.is_empty());
+        assert_eq!(downsampled.edge.len(), 1);
+        assert_eq!((downsampled.edge[0].from, downsampled.edge[0].to), (1, 4));
+        let chr1 = &downsampled.path[0];
+        let coordinates: Vec<Option<u64>> =
+            chr1.mapping.iter().map(|t| t.position.coordinate).collect();
+        assert_eq!(coordinates, vec![Some(0), Some(5)]);
+        let alt1 = &downsampled.path[1];
+        let alt1_ids: Vec<u64> = alt1.mapping.iter().map(|t| t.position.node_id).collect();
+        assert_eq!(alt1_ids, vec![1, 4]);
+        assert_eq!(downsample_graph(&subgraph, "chr9", 2), VgGraph::default());
+    }
+}
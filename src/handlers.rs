@@ -4,21 +4,35 @@ extern crate params;
 extern crate serde_json;
 
 use iron_send_file::send_file;
-use std::error::Error;
 use std::ffi::OsStr;
-use std::fs::{metadata, File};
-use std::io::Read;
+use std::fs::{self, metadata, File};
+use std::io;
+use std::io::{ErrorKind, Read};
+use std::panic;
+use std::panic::AssertUnwindSafe;
 use std::path::Path;
 
 use crate::annotations::*;
+use crate::cache;
+use crate::cache::{
+    generate_graph_cache, graph_cache_filename, graph_identity, uploaded_xg, CacheManager,
+};
+use crate::error::ApiError;
+use crate::graph::GraphFormat;
+use crate::jobs;
+use crate::jobs::Jobs;
 
 use crate::handlers::params::{Params, Value};
 use iron::headers::ContentType;
+use iron::mime::{Mime, SubLevel, TopLevel};
 use iron::modifiers::Redirect;
 use iron::prelude::*;
-use iron::{status, AfterMiddleware, Handler, IronResult, Request, Response};
-use crate::lib::{Config, Database, OptionalRegion, Region};
+use iron::{status, AfterMiddleware, Handler, IronResult, Request, Response, Url};
+use crate::lib::{Config, ConfigData, Database, GeneNameTree, OptionalRegion, Region};
+use crate::pool::WorkPool;
+use crate::sequence::reference_sequence;
 use multipart::server::save::Entries;
+use multipart::server::save::PartialReason;
 use multipart::server::save::SaveResult;
 use multipart::server::Multipart;
 use router::Router;
@@ -26,43 +40,24 @@ use std::collections::BTreeMap;
 use std::sync::Arc;
 use crate::utils::url_compose;
 use crate::utils::time;
-use crate::vg::{Graph, GraphDB};
+use crate::summary::default_resolution;
+use crate::vg::{check_interval, Graph, GraphDB, VCF2XG_SCRIPT};
 use crate::Args;
 
 /// Match a `Result` into its inner value or
-/// return `500 Internal Server Error`,
-/// or some other provided error using the second variant of this macro.
+/// return the error converted into an `ApiError`,
+/// or the `ApiError` made from the error message by the second variant of this macro.
 macro_rules! try_handler {
     ( $e:expr ) => {
         match $e {
             Ok(x) => x,
-            Err(e) => {
-                return Ok(Response::with((
-                    status::InternalServerError,
-                    e.description(),
-                )))
-            }
-        }
-    };
-    ( $e:expr, $error:expr ) => {
-        match $e {
-            Ok(x) => x,
-            Err(e) => return Ok(Response::with(($error, e.description()))),
-        }
-    };
-}
-
-macro_rules! try_handler_string {
-    ( $e:expr ) => {
-        match $e {
-            Ok(x) => x,
-            Err(e) => return Ok(Response::with((status::InternalServerError, e))),
+            Err(e) => return Ok(ApiError::from(e).response()),
         }
     };
     ( $e:expr, $error:expr ) => {
         match $e {
             Ok(x) => x,
-            Err(e) => return Ok(Response::with(($error, e))),
+            Err(e) => return Ok(($error)(e.to_string()).response()),
         }
     };
 }
@@ -75,28 +70,28 @@ macro_rules! get_http_param {
         match $r.extensions.get::<Router>() {
             Some(router) => match router.find($e) {
                 Some(val) => val,
-                None => return Ok(Response::with(status::BadRequest)),
+                None => return Ok(ApiError::MissingParameter($e.to_string()).response()),
             },
-            None => return Ok(Response::with(status::InternalServerError)),
+            None => return Ok(ApiError::Internal("No router".to_string()).response()),
         }
     };
 }
 
 /// Get the value of a parameter in the URI.
 /// If the parameter was absent, return `400 Bad Request`.
-/// If we could not obtain the parameter list, return `500 Internal Server Error`.
+/// If we could not parse the parameter list, return `400 Bad Request`.
 macro_rules! get_param_str {
     ( $r:expr, $e:expr ) => {
-        match try_handler!($r.get_ref::<Params>()).get($e) {
+        match try_handler!($r.get_ref::<Params>(), ApiError::InvalidRequest).get($e) {
             Some(&Value::String(ref val)) => val.as_ref(),
-            _ => return Ok(Response::with((status::BadRequest))),
+            _ => return Ok(ApiError::MissingParameter($e.to_string()).response()),
         };
     };
 }
 
 macro_rules! get_param_optional_str {
     ( $r:expr, $e:expr ) => {
-        match try_handler!($r.get_ref::<Params>()).get($e) {
+        match try_handler!($r.get_ref::<Params>(), ApiError::InvalidRequest).get($e) {
             Some(&Value::String(ref val)) => Some(val.clone()),
             _ => None,
         };
@@ -105,7 +100,7 @@ macro_rules! get_param_optional_str {
 
 macro_rules! get_param_boolean {
     ( $r:expr, $e:expr ) => {
-        match try_handler!($r.get_ref::<Params>()).get($e) {
+        match try_handler!($r.get_ref::<Params>(), ApiError::InvalidRequest).get($e) {
             Some(&Value::String(ref val)) => match val.as_ref() {
                 "false" => false,
                 _ => true,
@@ -115,30 +110,136 @@ macro_rules! get_param_boolean {
     };
 }
 
+/// Select the `ConfigData` named by the `dataset` parameter.
+/// If the parameter was absent, the first dataset is used.
+/// If no dataset has the given name, return `404 Not Found`.
+macro_rules! get_dataset {
+    ( $r:expr, $config:expr ) => {{
+        let name = get_param_optional_str!($r, "dataset");
+        match $config.dataset(name.as_ref()) {
+            Some(data) => data,
+            None => return Ok(ApiError::UnknownDataset(name.unwrap_or_default()).response()),
+        }
+    }};
+}
+
+// Bytes of an uploaded file.
+const MAX_UPLOAD_SIZE: u64 = 1000000000;
+
+// Seconds between sweeps of the cache folder.
+const CACHE_SWEEP_INTERVAL: u64 = 600;
+
 pub struct Handlers {
+    pub datasets: DatasetsHandler,
     pub ranged_cache: RangedHandler,
     pub feature: FeatureHandler,
     pub region: RegionHandler,
+    pub nodes: NodesHandler,
+    pub sequence: SequenceHandler,
     pub graph: GraphHandler,
+    pub svg: GraphHandler,
+    pub summary: SummaryHandler,
     pub overview: OverViewHandler,
     pub multi_part: MultiPartHandler,
+    pub jobs: JobsHandler,
     pub upload: UploadHandler,
+    pub cache_stats: CacheStatsHandler,
 }
 
 impl Handlers {
-    pub fn new(config: Config, args: Args, database: Database) -> Handlers {
+    // Fails if the job directory under `--tmp` cannot be opened.
+    pub fn new(config: Config, args: Args, database: Database) -> io::Result<Handlers> {
         let config = Arc::new(config);
         let database = Arc::new(database);
         let args = Arc::new(args);
-        Handlers {
+        let jobs = Arc::new(Jobs::new(
+            Path::new(&format!("{}/jobs", &args.flag_tmp)),
+            config.bin.convert_timeout(),
+        )?);
+        jobs::start(&jobs, args.flag_jobs);
+        let cache_manager = Arc::new(CacheManager::new(
+            Path::new(&args.flag_tmp),
+            args.flag_tmp_size,
+            args.flag_tmp_ttl,
+            jobs.clone(),
+        ));
+        cache::start(&cache_manager, CACHE_SWEEP_INTERVAL);
+        let pool = Arc::new(WorkPool::new(args.flag_heavy, args.flag_heavy));
+        Ok(Handlers {
+            datasets: DatasetsHandler::new(config.clone()),
             ranged_cache: RangedHandler::new(config.clone()),
             feature: FeatureHandler::new(config.clone(), database.clone()),
             region: RegionHandler::new(config.clone(), database.clone()),
-            graph: GraphHandler::new(config.clone(), args.clone(), database.clone()),
+            nodes: NodesHandler::new(config.clone(), args.clone(), database.clone()),
+            sequence: SequenceHandler::new(config.clone(), args.clone(), database.clone()),
+            graph: GraphHandler::new(
+                config.clone(),
+                args.clone(),
+                database.clone(),
+                cache_manager.clone(),
+                pool.clone(),
+                false,
+            ),
+            svg: GraphHandler::new(
+                config.clone(),
+                args.clone(),
+                database.clone(),
+                cache_manager.clone(),
+                pool.clone(),
+                true,
+            ),
+            summary: SummaryHandler::new(
+                config.clone(),
+                args.clone(),
+                database.clone(),
+                pool.clone(),
+            ),
             overview: OverViewHandler::new(config.clone(), args.clone()),
-            multi_part: MultiPartHandler::new(args.clone(), config.clone(), database.clone()),
-            upload: UploadHandler::new(config.clone(), args.clone(), database.clone()),
-        }
+            multi_part: MultiPartHandler::new(
+                args.clone(),
+                config.clone(),
+                database.clone(),
+                jobs.clone(),
+            ),
+            jobs: JobsHandler::new(jobs.clone()),
+            upload: UploadHandler::new(
+                config.clone(),
+                args.clone(),
+                database.clone(),
+                pool.clone(),
+            ),
+            cache_stats: CacheStatsHandler::new(cache_manager.clone()),
+        })
+    }
+}
+
+pub struct DatasetsHandler {
+    config: Arc<Config>,
+}
+
+impl DatasetsHandler {
+    fn new(config: Arc<Config>) -> DatasetsHandler {
+        DatasetsHandler { config: config }
+    }
+}
+
+impl Handler for DatasetsHandler {
+    fn handle(&self, _: &mut Request) -> IronResult<Response> {
+        let datasets: Vec<_> = self
+            .config
+            .data
+            .iter()
+            .map(|data| {
+                json!({
+                    "name": data.name,
+                    "desc": data.desc,
+                    "ref_id": data.ref_id,
+                    "chr_prefix": data.chr_prefix,
+                })
+            })
+            .collect();
+        let post = try_handler!(serde_json::to_string(&datasets));
+        Ok(Response::with((status::Ok, post)))
     }
 }
 
@@ -154,9 +255,13 @@ impl RangedHandler {
 
 impl Handler for RangedHandler {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let data = get_dataset!(req, self.config);
         let ref name = get_http_param!(req, "filename");
-        let ref features = *self.config.data[0].features;
-        let ref item = features.iter().find(|&x| x.name == *name).unwrap();
+        let ref features = *data.features;
+        let ref item = match features.iter().find(|&x| x.name == *name) {
+            Some(item) => item,
+            None => return Ok(ApiError::UnknownFile(name.to_string()).response()),
+        };
 
         let path = Path::new(&item.url);
         let res = Response::new();
@@ -181,6 +286,7 @@ impl OverViewHandler {
 impl Handler for OverViewHandler {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
         let ref url_str = &req.url.clone().into();
+        let data = get_dataset!(req, self.config);
         let uuid = get_param_optional_str!(req, "uuid");
         let source = get_param_str!(req, "source");
         let tempdir = format!("cache/xg/");
@@ -189,17 +295,17 @@ impl Handler for OverViewHandler {
             "features" => {
                 match uuid {
                     Some(ref file) => {
-                        let url = try_handler_string!(url_compose(
+                        let url = try_handler!(url_compose(
                             &url_str,
                             &(self.args.flag_api.to_string() + &tempdir + &file + ".pcf")
                         )); //
                         Ok(Response::with((status::Found, Redirect(url))))
                     }
                     None => {
-                        match self.config.data[0].source.csv {
+                        match data.source.csv {
                             Some(ref file) => {
                                 //TODO() Auto Truncate if the row length exceeds 20,000.
-                                let url = try_handler_string!(url_compose(
+                                let url = try_handler!(url_compose(
                                     &url_str,
                                     &(self.args.flag_api.to_string() + &file)
                                 ));
@@ -212,14 +318,13 @@ impl Handler for OverViewHandler {
             }
             "chromosomes" => {
                 let ref file = self.config.reference.chroms;
-                let url = try_handler_string!(url_compose(
+                let url = try_handler!(url_compose(
                     &url_str,
                     &(self.args.flag_api.to_string() + &file)
                 ));
                 Ok(Response::with((status::Found, Redirect(url))))
             }
             "metadata" => {
-                let data = &self.config.data[0];
                 let json = json!({
                     "name": data.name,
                     "desc": data.desc,
@@ -228,10 +333,14 @@ impl Handler for OverViewHandler {
                     "static_files": data.static_files,
                     "alignments": data.source.gamindex.is_some()
                 });
-                let post = try_handler!(serde_json::to_string(&json), status::BadRequest);
+                let post = try_handler!(serde_json::to_string(&json));
                 return Ok(Response::with((status::Ok, post)));
             }
-            _ => Ok(Response::with(status::BadRequest)),
+            _ => Ok(ApiError::InvalidParameter(
+                "source".to_string(),
+                format!("{} is not one of features, chromosomes or metadata", source),
+            )
+            .response()),
         }
     }
 }
@@ -252,11 +361,16 @@ impl RegionHandler {
 
 impl Handler for RegionHandler {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let data = get_dataset!(req, self.config);
         let ref format: String =
             get_param_optional_str!(req, "format").unwrap_or("bed".to_string());
         let ref multiple: Option<String> = get_param_optional_str!(req, "multiple");
         let ref bins: Option<u32> =
             get_param_optional_str!(req, "bins").and_then(|t| t.parse::<u32>().ok());
+        let stat = get_param_optional_str!(req, "stat");
+        let stat = try_handler!(BigWigStat::new(stat.as_ref().map(|t| t.as_str())), |e| {
+            ApiError::InvalidParameter("stat".to_string(), e)
+        });
         let ref path: &str = get_param_str!(req, "path");
         if let Some(_) = multiple {
             //let Some(_) = path.to_string().find(",") {
@@ -266,35 +380,42 @@ impl Handler for RegionHandler {
                 .flat_map(|a| {
                     Region::new_with_prefix(
                         a.to_string()
-                            .trim_start_matches(&self.config.data[0].chr_prefix)
+                            .trim_start_matches(&data.chr_prefix)
                             .to_string(),
                         &"".to_string(),
                     )
                 })
                 .collect();
             let features = regions_to_feature_map(
-                &self.config.clone(),
+                data,
+                &self.database,
                 &format.to_string(),
                 path_vector,
                 *bins,
+                stat,
             );
-            let post = try_handler!(serde_json::to_string(&features), status::BadRequest);
+            let post = try_handler!(serde_json::to_string(&features));
             Ok(Response::with((status::Ok, post)))
         } else {
-            let path_struct: Region = try_handler!(Region::new_with_prefix(
-                path.to_string()
-                    .trim_start_matches(&self.config.data[0].chr_prefix)
-                    .to_string(),
-                &"".to_string()
-            ));
+            let path_struct: Region = try_handler!(
+                Region::new_with_prefix(
+                    path.to_string()
+                        .trim_start_matches(&data.chr_prefix)
+                        .to_string(),
+                    &"".to_string()
+                ),
+                |e| ApiError::InvalidRegion("path".to_string(), e)
+            );
 
             let features = region_to_feature_map(
-                &self.config.clone(),
+                data,
+                &self.database,
                 &format.to_string(),
                 path_struct,
                 *bins,
+                stat,
             );
-            let post = try_handler!(serde_json::to_string(&features), status::BadRequest);
+            let post = try_handler!(serde_json::to_string(&features));
             Ok(Response::with((status::Ok, post)))
         }
     }
@@ -316,19 +437,28 @@ impl FeatureHandler {
 
 impl Handler for FeatureHandler {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let data = get_dataset!(req, self.config);
         let starts_with: Option<String> = get_param_optional_str!(req, "startsWith");
         let equals: Option<String> = get_param_optional_str!(req, "equals");
-        let reference: String =
-            get_param_optional_str!(req, "ref").unwrap_or(self.config.data[0].ref_id.clone());
+        let ref_param: Option<String> = get_param_optional_str!(req, "ref");
+        let reference: String = ref_param.clone().unwrap_or(data.ref_id.clone());
+        let empty = GeneNameTree::new();
+        let genes = match self.database.gene_name_tree.get(&reference) {
+            Some(genes) => genes,
+            // References without annotations have no gene names.
+            None if ref_param.is_none() => &empty,
+            None => {
+                return Ok(ApiError::InvalidParameter(
+                    "ref".to_string(),
+                    format!("Unknown reference: {}", reference),
+                )
+                .response())
+            }
+        };
         match starts_with {
             Some(starts) => {
                 let mut tmpvec: Vec<String> = Vec::new();
-                let mut feature = self
-                    .database
-                    .gene_name_tree
-                    .get(&reference)
-                    .unwrap()
-                    .range(starts.to_string()..);
+                let mut feature = genes.range(starts.to_string()..);
                 while feature
                     .next()
                     .and_then(|tuple| match tuple.0.starts_with(&starts) {
@@ -340,56 +470,181 @@ impl Handler for FeatureHandler {
                     })
                     .is_some()
                 {}
-                let retval = try_handler!(serde_json::to_string(&tmpvec), status::BadRequest);
+                let retval = try_handler!(serde_json::to_string(&tmpvec));
                 Ok(Response::with((status::Ok, retval)))
             }
             None => match equals {
                 Some(equals) => match equals.parse::<u64>() {
-                    Ok(number) => match node_id_to_region(self.database.clone(), number) {
+                    Ok(number) => match node_id_to_region(data, self.database.clone(), number) {
                         Some(region) => {
-                            let retval =
-                                try_handler!(serde_json::to_string(&region), status::BadRequest);
+                            let retval = try_handler!(serde_json::to_string(&region));
                             Ok(Response::with((status::Ok, retval)))
                         }
-                        None => Ok(Response::with(status::NoContent)),
+                        None => Ok(ApiError::UnknownNode("equals".to_string(), number).response()),
                     },
-                    Err(_) => {
-                        let feature_opt = self
-                            .database
-                            .gene_name_tree
-                            .get(&reference)
-                            .unwrap()
-                            .get(&equals);
-                        match feature_opt {
-                            Some(feature) => {
-                                let retval = try_handler!(
-                                    serde_json::to_string(&(equals, feature)),
-                                    status::BadRequest
-                                );
-                                Ok(Response::with((status::Ok, retval)))
-                            }
-                            None => Ok(Response::with(status::NoContent)),
+                    Err(_) => match genes.get(&equals) {
+                        Some(feature) => {
+                            let retval = try_handler!(serde_json::to_string(&(equals, feature)));
+                            Ok(Response::with((status::Ok, retval)))
                         }
-                    }
+                        None => Ok(ApiError::UnknownGene("equals".to_string(), equals).response()),
+                    },
                 },
-                None => Ok(Response::with(status::BadRequest)),
+                None => Ok(ApiError::InvalidRequest(
+                    "Either `startsWith` or `equals` is required".to_string(),
+                )
+                .response()),
             },
         }
     }
 }
 
+pub struct NodesHandler {
+    config: Arc<Config>,
+    args: Arc<Args>,
+    database: Arc<Database>,
+}
+
+impl NodesHandler {
+    fn new(config: Arc<Config>, args: Arc<Args>, database: Arc<Database>) -> NodesHandler {
+        NodesHandler {
+            config: config,
+            args: args,
+            database: database,
+        }
+    }
+}
+
+impl Handler for NodesHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let data = get_dataset!(req, self.config);
+        let path: &str = get_param_str!(req, "path");
+        let region: Region = try_handler!(
+            Region::new_with_prefix(
+                path.trim_start_matches(&data.chr_prefix).to_string(),
+                &"".to_string()
+            ),
+            |e| ApiError::InvalidRegion("path".to_string(), e)
+        );
+        let max_interval = data.max_interval(&self.args.flag_interval);
+        let interval = region.stop.saturating_sub(region.start);
+        if interval > max_interval {
+            return Ok(
+                ApiError::RegionTooLarge("path".to_string(), interval, max_interval).response(),
+            );
+        }
+        let nodes: Vec<_> = match self.database.rocks.get(&data.name) {
+            Some(index) if !index.has_coordinates() => {
+                return Ok(ApiError::Unsupported(format!(
+                    "{} has no coordinate index; rebuild it with --reinitrocks",
+                    index.path()
+                ))
+                .response())
+            }
+            Some(index) => index
+                .nodes(&region)
+                .into_iter()
+                .map(|(id, coord)| json!({"id": id, "start": coord.start, "stop": coord.stop}))
+                .collect(),
+            None => vec![],
+        };
+        let post = try_handler!(serde_json::to_string(&nodes));
+        Ok(Response::with((status::Ok, post)))
+    }
+}
+
+pub struct SequenceHandler {
+    config: Arc<Config>,
+    args: Arc<Args>,
+    database: Arc<Database>,
+}
+
+impl SequenceHandler {
+    fn new(config: Arc<Config>, args: Arc<Args>, database: Arc<Database>) -> SequenceHandler {
+        SequenceHandler {
+            config: config,
+            args: args,
+            database: database,
+        }
+    }
+}
+
+impl Handler for SequenceHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let data = get_dataset!(req, self.config);
+        let reverse = get_param_optional_str!(req, "strand") == Some("-".to_string());
+        if let Some(node) = get_param_optional_str!(req, "node") {
+            let node_id = try_handler!(node.parse::<u64>(), |e| ApiError::InvalidParameter(
+                "node".to_string(),
+                e
+            ));
+            let graph = self.database.graph.as_graph();
+            return match try_handler!(graph.node_sequence(
+                &self.config,
+                data,
+                &self.database,
+                node_id
+            )) {
+                Some(sequence) => {
+                    let json = json!({"node": node_id, "sequence": sequence});
+                    let post = try_handler!(serde_json::to_string(&json));
+                    Ok(Response::with((status::Ok, post)))
+                }
+                None => Ok(ApiError::UnknownNode("node".to_string(), node_id).response()),
+            };
+        }
+        let path: &str = get_param_str!(req, "path");
+        let region: Region = try_handler!(
+            Region::new_with_prefix(
+                path.trim_start_matches(&data.chr_prefix).to_string(),
+                &"".to_string()
+            ),
+            |e| ApiError::InvalidRegion("path".to_string(), e)
+        );
+        let max_interval = data.max_interval(&self.args.flag_interval);
+        let interval = region.stop.saturating_sub(region.start);
+        if interval > max_interval {
+            return Ok(
+                ApiError::RegionTooLarge("path".to_string(), interval, max_interval).response(),
+            );
+        }
+        let sequence = try_handler!(reference_sequence(
+            data,
+            self.database.twobits.get(&data.name),
+            &region,
+            reverse
+        ));
+        let json = json!({
+            "path": data.chr_prefix.clone() + &region.path,
+            "start": region.start,
+            "stop": region.stop,
+            "strand": if reverse { "-" } else { "+" },
+            "sequence": sequence
+        });
+        let post = try_handler!(serde_json::to_string(&json));
+        Ok(Response::with((status::Ok, post)))
+    }
+}
+
 pub struct MultiPartHandler {
     config: Arc<Config>,
     database: Arc<Database>,
     args: Arc<Args>,
+    jobs: Arc<Jobs>,
 }
 
 impl MultiPartHandler {
-    fn new(args: Arc<Args>, config: Arc<Config>, database: Arc<Database>) -> MultiPartHandler {
+    fn new(
+        args: Arc<Args>,
+        config: Arc<Config>,
+        database: Arc<Database>,
+        jobs: Arc<Jobs>,
+    ) -> MultiPartHandler {
         MultiPartHandler {
             args: args,
             config: config,
             database: database,
+            jobs: jobs,
         }
     }
 
@@ -409,48 +664,69 @@ impl MultiPartHandler {
         if let Some(files) = entries.files.get("file") {
             if let Some(file) = files.first() {
                 let mut json = BTreeMap::new();
-                let path = Path::new(&file.path).file_name().unwrap().to_string_lossy();
+                let path = match Path::new(&file.path).file_name() {
+                    Some(path) => path.to_string_lossy(),
+                    None => {
+                        return Ok(
+                            ApiError::InvalidRequest("The file has no name".to_string()).response()
+                        )
+                    }
+                };
                 match entries.fields.get("json") {
                     Some(_) => {
                         // when vg's json file
-                        let mut file = File::open(&file.path).unwrap();
+                        let mut file = try_handler!(File::open(&file.path));
                         let mut contents = String::new();
-                        file.read_to_string(&mut contents).unwrap();
+                        try_handler!(file.read_to_string(&mut contents));
                         return Ok(Response::with((status::Ok, contents)));
                     }
                     None => {
                         // when vcf file
                         json.insert("remote_file", path.clone());
-                        let post = try_handler!(serde_json::to_string(&json), status::BadRequest);
                         let _cache_filename = time().to_string();
                         let reference = entries.fields.get("ref");
-                        // Fork post option.
-                        match self.database.graph {
-                            GraphDB::VG(ref vg) => {
-                                if let Err(_) = vg.spawn_vcf_for_visualize(
-                                    &file.path.to_string_lossy().into_owned(),
-                                    &path.to_string(),
-                                    &self.config,
-                                    &tempdir,
-                                    Path::new(&file.filename.clone().unwrap()).extension()
-                                        == Some(OsStr::new("pcf")),
-                                    reference,
-                                    file.filename.as_ref(),
-                                ) {
-                                    debug!("Error on spawning vcf for visulization");
-                                }
+                        // Convert it in background.
+                        let command = match self.database.graph {
+                            GraphDB::VG(ref vg) => vg.vcf_for_visualize_command(
+                                &file.path.to_string_lossy().into_owned(),
+                                &path.to_string(),
+                                &self.config,
+                                &tempdir,
+                                Path::new(&file.filename.clone().unwrap_or_default()).extension()
+                                    == Some(OsStr::new("pcf")),
+                                reference,
+                                file.filename.as_ref(),
+                            ),
+                            GraphDB::Native(_) | GraphDB::Gfa(_) => {
+                                let _ = fs::remove_file(&file.path);
+                                return Ok(ApiError::Unsupported(
+                                    "VCF conversion requires the vg backend".to_string(),
+                                )
+                                .response());
                             }
+                        };
+                        // Refused uploads are not left under `--tmp`.
+                        if !Path::new(VCF2XG_SCRIPT).exists() {
+                            let _ = fs::remove_file(&file.path);
+                            return Ok(ApiError::Unsupported(format!(
+                                "VCF conversion is unavailable, as {} is not installed",
+                                VCF2XG_SCRIPT
+                            ))
+                            .response());
                         }
+                        let job = self.jobs.submit(path.to_string(), command);
+                        json.insert("job", job.id.into());
+                        let post = try_handler!(serde_json::to_string(&json));
                         return Ok(Response::with((status::Ok, post)));
                     }
                 }
             }
         }
 
-        Ok(Response::with((
-            status::BadRequest,
-            "The request is not including multipart data.",
-        )))
+        Ok(
+            ApiError::InvalidRequest("The request is not including multipart data.".to_string())
+                .response(),
+        )
     }
 }
 
@@ -464,41 +740,86 @@ impl Handler for MultiPartHandler {
                 // in a new temporary directory under the OS temporary directory.
                 //let is_json: bool = get_param_boolean!(req, "json");
                 //FIXME() save().temp()
-                match multipart.save().size_limit(1000000000).with_dir(tempdir) {
+                match multipart
+                    .save()
+                    .size_limit(MAX_UPLOAD_SIZE)
+                    .with_dir(tempdir)
+                {
                     SaveResult::Full(entries) => self.process_entries(entries),
-                    SaveResult::Partial(entries, reason) => {
-                        self.process_entries(entries.keep_partial())?;
-                        Ok(Response::with((
-                            status::BadRequest,
-                            format!("error reading request: {}", reason.unwrap_err()),
-                        )))
+                    // A truncated upload is refused without converting it.
+                    SaveResult::Partial(_, reason) => Ok(match reason {
+                        PartialReason::SizeLimit => ApiError::UploadTooLarge(MAX_UPLOAD_SIZE),
+                        PartialReason::CountLimit => {
+                            ApiError::InvalidRequest("The request has too many fields".to_string())
+                        }
+                        reason => {
+                            ApiError::InvalidRequest(format!("error reading request: {:?}", reason))
+                        }
                     }
-                    SaveResult::Error(error) => Ok(Response::with((
-                        status::BadRequest,
-                        format!("error reading request: {}", error),
-                    ))),
+                    .response()),
+                    SaveResult::Error(error) => Ok(ApiError::InvalidRequest(format!(
+                        "error reading request: {}",
+                        error
+                    ))
+                    .response()),
                 }
             }
-            Err(_) => Ok(Response::with((
-                status::BadRequest,
-                "The request is not multipart",
-            ))),
+            Err(_) => {
+                Ok(ApiError::InvalidRequest("The request is not multipart".to_string()).response())
+            }
+        }
+    }
+}
+
+pub struct JobsHandler {
+    jobs: Arc<Jobs>,
+}
+
+impl JobsHandler {
+    fn new(jobs: Arc<Jobs>) -> JobsHandler {
+        JobsHandler { jobs: jobs }
+    }
+}
+
+impl Handler for JobsHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let id = get_http_param!(req, "id");
+        match self.jobs.get(id) {
+            Some(job) => {
+                let json = try_handler!(serde_json::to_string(&job));
+                Ok(Response::with((status::Ok, json)))
+            }
+            None => Ok(ApiError::UnknownJob(id.to_string()).response()),
         }
     }
 }
 
+// Datasets are looked up again by name on the workers, which need owned handles.
+fn dataset<'a>(config: &'a Config, name: &String) -> Result<&'a ConfigData, io::Error> {
+    config
+        .dataset(Some(name))
+        .ok_or(io::Error::new(ErrorKind::NotFound, "Unknown dataset"))
+}
+
 pub struct UploadHandler {
     config: Arc<Config>,
     database: Arc<Database>,
     args: Arc<Args>,
+    pool: Arc<WorkPool>,
 }
 
 impl UploadHandler {
-    fn new(config: Arc<Config>, args: Arc<Args>, database: Arc<Database>) -> UploadHandler {
+    fn new(
+        config: Arc<Config>,
+        args: Arc<Args>,
+        database: Arc<Database>,
+        pool: Arc<WorkPool>,
+    ) -> UploadHandler {
         UploadHandler {
             config: config,
             database: database,
             args: args,
+            pool: pool,
         }
     }
 }
@@ -511,18 +832,24 @@ impl Handler for UploadHandler {
         let ref json: Option<String> = get_param_optional_str!(req, "json");
         info!("json: {:?}", json);
         let ref xgfile: Option<String> = get_param_optional_str!(req, "xg");
+        let format = get_param_optional_str!(req, "format");
+        let format = try_handler!(
+            GraphFormat::new(false, format.as_ref().map(|t| t.as_str())),
+            |e| ApiError::InvalidParameter("format".to_string(), e)
+        );
         let ref url_str = &req.url.clone().into();
         info!("{}", url_str);
+        let data = get_dataset!(req, self.config);
         let ref path: &str = get_param_str!(req, "path");
-        let path_struct: OptionalRegion = try_handler!(OptionalRegion::new_with_prefix(
-            path.to_string(),
-            &self.config.data[0].chr_prefix
-        ));
+        let path_struct: OptionalRegion = try_handler!(
+            OptionalRegion::new_with_prefix(path.to_string(), &data.chr_prefix),
+            |e| ApiError::InvalidRegion("path".to_string(), e)
+        );
         info!("{}", path_struct);
-        let cache_filename = time().to_string() + ".json";
+        let cache_filename = time().to_string() + "." + format.extension();
         let cache_str = self.args.flag_tmp.clone() + "/" + &cache_filename;
         let cache_path = Path::new(&cache_str);
-        let url = try_handler_string!(url_compose(
+        let url = try_handler!(url_compose(
             &url_str,
             &(self.args.flag_api.clone() + "cache/" + &cache_filename)
         ));
@@ -532,25 +859,52 @@ impl Handler for UploadHandler {
             _ => {
                 let cache_file = try_handler!(File::create(cache_path));
                 match self.database.graph {
-                    GraphDB::VG(ref vg) => match path_struct.inverted() {
-                        Some(true) => Ok(Response::with(status::BadRequest)),
+                    GraphDB::VG(_) => match path_struct.inverted() {
+                        Some(true) => Ok(ApiError::InvalidRegion(
+                            "path".to_string(),
+                            "The region is inverted".to_string(),
+                        )
+                        .response()),
                         _ => {
-                            let generate_cache = try_handler!(vg.generate_graph_to_file_custom(
-                                path_struct,
-                                0,
-                                &cache_file,
-                                steps,
-                                &self.config,
-                                json,
-                                xgfile,
-                                &self.args
-                            ));
+                            let (config, database, args) = (
+                                self.config.clone(),
+                                self.database.clone(),
+                                self.args.clone(),
+                            );
+                            let (name, steps, json, xgfile) =
+                                (data.name.clone(), *steps, json.clone(), xgfile.clone());
+                            let generate_cache = self.pool.run(move || match database.graph {
+                                GraphDB::VG(ref vg) => vg.generate_graph_to_file_custom(
+                                    path_struct,
+                                    0,
+                                    &cache_file,
+                                    &steps,
+                                    &config,
+                                    dataset(&config, &name)?,
+                                    &json,
+                                    &xgfile,
+                                    &args,
+                                    format,
+                                ),
+                                _ => Ok(false),
+                            });
+                            let generate_cache = match generate_cache.and_then(|t| t) {
+                                Ok(generated) => generated,
+                                Err(e) => return Ok(ApiError::from(e).response()),
+                            };
                             match generate_cache {
                                 true => Ok(Response::with((status::Found, Redirect(url)))),
-                                false => Ok(Response::with(status::InternalServerError)),
+                                false => Ok(ApiError::Internal(
+                                    "The graph could not be generated".to_string(),
+                                )
+                                .response()),
                             }
                         }
                     },
+                    GraphDB::Native(_) | GraphDB::Gfa(_) => Ok(ApiError::Unsupported(
+                        "Uploaded graphs require the vg backend".to_string(),
+                    )
+                    .response()),
                 }
             }
         }
@@ -561,15 +915,42 @@ pub struct GraphHandler {
     config: Arc<Config>,
     database: Arc<Database>,
     args: Arc<Args>,
+    cache: Arc<CacheManager>,
+    pool: Arc<WorkPool>,
+    svg: bool, // Answer the rendered SVG itself, for `/render/svg`.
 }
 
 impl GraphHandler {
-    fn new(config: Arc<Config>, args: Arc<Args>, database: Arc<Database>) -> GraphHandler {
+    fn new(
+        config: Arc<Config>,
+        args: Arc<Args>,
+        database: Arc<Database>,
+        cache: Arc<CacheManager>,
+        pool: Arc<WorkPool>,
+        svg: bool,
+    ) -> GraphHandler {
         GraphHandler {
             config: config,
             database: database,
             args: args,
+            cache: cache,
+            pool: pool,
+            svg: svg,
+        }
+    }
+
+    // Redirect to the cache file, or answer the SVG in it.
+    fn found(&self, url: Url, cache_path: &Path) -> IronResult<Response> {
+        if !self.svg {
+            return Ok(Response::with((status::Found, Redirect(url))));
         }
+        let svg = try_handler!(fs::read(cache_path));
+        let mime = Mime(
+            TopLevel::Image,
+            SubLevel::Ext("svg+xml".to_string()),
+            vec![],
+        );
+        Ok(Response::with((status::Ok, mime, svg)))
     }
 }
 
@@ -579,119 +960,261 @@ impl Handler for GraphHandler {
         let ref _length =
             get_param_optional_str!(req, "length").and_then(|t| t.parse::<i64>().ok());
         let raw: bool = get_param_boolean!(req, "raw");
+        let format = get_param_optional_str!(req, "format");
+        let format = match self.svg {
+            true => GraphFormat::Svg,
+            false => try_handler!(
+                GraphFormat::new(raw, format.as_ref().map(|t| t.as_str())),
+                |e| ApiError::InvalidParameter("format".to_string(), e)
+            ),
+        };
         let cache: bool = get_param_boolean!(req, "cache");
         let gam: bool = get_param_boolean!(req, "gam");
+        let downsample: bool = get_param_boolean!(req, "downsample");
         let uuid = get_param_optional_str!(req, "uuid");
         let ref url_str = &req.url.clone().into();
+        let data = get_dataset!(req, self.config);
         let ref path: &str = get_param_str!(req, "path");
-        let path_struct: OptionalRegion = match uuid {
-            Some(_) => try_handler!(OptionalRegion::new(path.to_string())),
-            None => try_handler!(OptionalRegion::new_with_prefix(
-                path.to_string(),
-                &self.config.data[0].chr_prefix
-            )),
-        };
+        let path_struct: OptionalRegion = try_handler!(
+            match uuid {
+                Some(_) => OptionalRegion::new(path.to_string()),
+                None => OptionalRegion::new_with_prefix(path.to_string(), &data.chr_prefix),
+            },
+            |e| ApiError::InvalidRegion("path".to_string(), e)
+        );
         info!("Range: {}", path_struct);
-        let cache_filename = (if raw { "raw_" } else { "" }).to_string()
-            + &uuid.clone().map(|t| t + "_").unwrap_or("".to_string())
-            + &path_struct.uuid()
-            + ".json";
+        let downsampled = try_handler!(check_interval(
+            &path_struct,
+            data.max_interval(&self.args.flag_interval),
+            downsample
+        ));
+        // Merged nodes keep only a part of their bases, which GFA and FASTA would write as is.
+        if downsampled && (format == GraphFormat::Gfa || format == GraphFormat::Fasta) {
+            return Ok(ApiError::Unsupported(
+                "`downsample` is not supported for GFA and FASTA".to_string(),
+            )
+            .response());
+        }
+        let identity = graph_identity(
+            data,
+            self.database.version,
+            &self.args,
+            &path_struct,
+            &uuid,
+            gam,
+        );
+        let cache_filename = graph_cache_filename(
+            format,
+            &uuid,
+            &path_struct,
+            steps,
+            gam,
+            downsampled,
+            &identity,
+        );
         let cache_str = self.args.flag_tmp.clone() + "/" + &cache_filename;
         let cache_path = Path::new(&cache_str);
-        let url = try_handler_string!(url_compose(
+        let url = try_handler!(url_compose(
             &url_str,
             &(self.args.flag_api.clone() + "cache/" + &cache_filename)
         ));
         debug!("Redirect URL: {}, {}", url, cache_str);
+        if let Some(ref uuid_exist) = uuid {
+            let xg = uploaded_xg(&self.args, uuid_exist);
+            if metadata(&xg).is_err() {
+                return Ok(ApiError::UnknownUuid(uuid_exist.clone()).response());
+            }
+            self.cache.touch(Path::new(&xg));
+        }
         match metadata(cache_path) {
-            Ok(ref n) if cache && n.len() > 1 => Ok(Response::with((status::Found, Redirect(url)))),
-            _ => {
-                let cache_file = try_handler!(File::create(cache_path));
-                match self.database.graph {
-                    GraphDB::VG(ref vg) => match path_struct.inverted() {
-                        Some(true) => Ok(Response::with((
-                            status::BadRequest,
-                            "Path region is inverted",
-                        ))),
-                        _ => match uuid {
-                            Some(uuid_exist) => {
-                                debug!("uuid: {}", uuid_exist);
-                                let generate_cache = match raw {
-                                    false => try_handler!(vg.generate_graph_to_file(
-                                        path_struct,
-                                        0,
-                                        &cache_file,
-                                        steps,
-                                        &self.config,
-                                        &format!("/{}/xg/{}.xg", &self.args.flag_tmp, uuid_exist),
-                                        true,
-                                        &self.args.flag_interval
-                                    )),
-                                    true => try_handler!(vg.generate_graph_to_file_wo_helper(
-                                        path_struct,
-                                        0,
-                                        &cache_path,
-                                        steps,
-                                        &self.config,
-                                        &format!("/{}/xg/{}.xg", &self.args.flag_tmp, uuid_exist),
-                                        true,
-                                        &self.args.flag_interval,
-                                        gam,
-                                        self.database.version
-                                    )),
-                                };
-                                match generate_cache {
-                                    true => Ok(Response::with((status::Found, Redirect(url)))),
-                                    false => Ok(Response::with(status::InternalServerError)),
-                                }
-                            }
-                            None => {
-                                let generate_cache = match raw {
-                                    false => try_handler!(vg.generate_graph_to_file(
-                                        path_struct,
-                                        0,
-                                        &cache_file,
-                                        steps,
-                                        &self.config,
-                                        &self.config.data[0].source.xg,
-                                        false,
-                                        &self.args.flag_interval
-                                    )),
-                                    true => try_handler!(vg.generate_graph_to_file_wo_helper(
-                                        path_struct,
-                                        0,
-                                        &cache_path,
-                                        steps,
-                                        &self.config,
-                                        &self.config.data[0].source.xg,
-                                        false,
-                                        &self.args.flag_interval,
-                                        gam,
-                                        self.database.version
-                                    )),
-                                };
-                                match generate_cache {
-                                    true => Ok(Response::with((status::Found, Redirect(url)))),
-                                    false => Ok(Response::with(status::InternalServerError)),
-                                }
-                            }
-                        },
-                    },
-                }
+            Ok(ref n) if cache && n.len() > 1 => {
+                self.cache.hit(cache_path);
+                self.found(url, cache_path)
             }
+            _ => match path_struct.inverted() {
+                Some(true) => Ok(ApiError::InvalidRegion(
+                    "path".to_string(),
+                    "The region is inverted".to_string(),
+                )
+                .response()),
+                _ => {
+                    self.cache.miss();
+                    let (config, database, args) = (
+                        self.config.clone(),
+                        self.database.clone(),
+                        self.args.clone(),
+                    );
+                    let (name, steps, uuid, cache_str) =
+                        (data.name.clone(), *steps, uuid.clone(), cache_str.clone());
+                    // Identical requests in flight wait for the first one, which runs on the pool.
+                    let generate_cache = self.cache.coalesce(&cache_filename, || {
+                        self.pool
+                            .run(move || {
+                                generate_graph_cache(
+                                    &config,
+                                    dataset(&config, &name)?,
+                                    &database,
+                                    &args,
+                                    path_struct,
+                                    &steps,
+                                    format,
+                                    gam,
+                                    downsample,
+                                    &uuid,
+                                    Path::new(&cache_str),
+                                )
+                            })
+                            .and_then(|t| t)
+                    });
+                    let generate_cache = match generate_cache {
+                        Ok(generated) => generated,
+                        Err(e) => return Ok(ApiError::from(e).response()),
+                    };
+                    self.cache.touch(cache_path);
+                    match generate_cache {
+                        true => self.found(url, cache_path),
+                        false => Ok(ApiError::Internal(
+                            "The graph could not be generated".to_string(),
+                        )
+                        .response()),
+                    }
+                }
+            },
         }
     }
 }
 
+pub struct SummaryHandler {
+    config: Arc<Config>,
+    args: Arc<Args>,
+    database: Arc<Database>,
+    pool: Arc<WorkPool>,
+}
+
+impl SummaryHandler {
+    fn new(
+        config: Arc<Config>,
+        args: Arc<Args>,
+        database: Arc<Database>,
+        pool: Arc<WorkPool>,
+    ) -> SummaryHandler {
+        SummaryHandler {
+            config: config,
+            args: args,
+            database: database,
+            pool: pool,
+        }
+    }
+}
+
+impl Handler for SummaryHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let steps = get_param_optional_str!(req, "steps").and_then(|t| t.parse::<i64>().ok());
+        let resolution = get_param_optional_str!(req, "resolution");
+        let data = get_dataset!(req, self.config);
+        let path: &str = get_param_str!(req, "path");
+        let path_struct: OptionalRegion = try_handler!(
+            OptionalRegion::new_with_prefix(path.to_string(), &data.chr_prefix),
+            |e| ApiError::InvalidRegion("path".to_string(), e)
+        );
+        if let Some(true) = path_struct.inverted() {
+            return Ok(ApiError::InvalidRegion(
+                "path".to_string(),
+                "The region is inverted".to_string(),
+            )
+            .response());
+        }
+        let max_interval = data.max_interval(&self.args.flag_interval);
+        try_handler!(check_interval(&path_struct, max_interval, true));
+        // Without a resolution, the region is summarized into about a thousand super-nodes.
+        let resolution = match resolution {
+            Some(resolution) => try_handler!(resolution.parse::<u64>(), |e| {
+                ApiError::InvalidParameter("resolution".to_string(), e)
+            }),
+            None => default_resolution(path_struct.interval().unwrap_or(0)),
+        };
+        let (config, database, name) = (
+            self.config.clone(),
+            self.database.clone(),
+            data.name.clone(),
+        );
+        let summary = self.pool.run(move || {
+            database.graph.as_graph().generate_summary(
+                &path_struct,
+                &steps,
+                &config,
+                dataset(&config, &name)?,
+                &database,
+                max_interval,
+                resolution,
+            )
+        });
+        let summary = match summary.and_then(|t| t) {
+            Ok(summary) => summary,
+            Err(e) => return Ok(ApiError::from(e).response()),
+        };
+        let post = try_handler!(serde_json::to_string(&summary));
+        Ok(Response::with((status::Ok, post)))
+    }
+}
+
+pub struct CacheStatsHandler {
+    cache: Arc<CacheManager>,
+}
+
+impl CacheStatsHandler {
+    fn new(cache: Arc<CacheManager>) -> CacheStatsHandler {
+        CacheStatsHandler { cache: cache }
+    }
+}
+
+impl Handler for CacheStatsHandler {
+    fn handle(&self, _: &mut Request) -> IronResult<Response> {
+        let post = try_handler!(serde_json::to_string(&self.cache.stats()));
+        Ok(Response::with((status::Ok, post)))
+    }
+}
+
 pub struct JsonAfterMiddleware;
 
 impl AfterMiddleware for JsonAfterMiddleware {
     fn after(&self, _: &mut Request, mut res: Response) -> IronResult<Response> {
-        res.headers.set(ContentType::json());
+        // Responses other than JSON, e.g. SVG, set their own type.
+        if !res.headers.has::<ContentType>() {
+            res.headers.set(ContentType::json());
+        }
         Ok(res)
     }
 }
 
+// A safety net for panics left in handlers, which answers 500 instead of dropping the connection.
+pub struct CatchPanic<H: Handler> {
+    handler: H,
+}
+
+impl<H: Handler> CatchPanic<H> {
+    pub fn new(handler: H) -> CatchPanic<H> {
+        CatchPanic { handler: handler }
+    }
+}
+
+impl<H: Handler> Handler for CatchPanic<H> {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        match panic::catch_unwind(AssertUnwindSafe(|| self.handler.handle(req))) {
+            Ok(res) => res,
+            Err(e) => {
+                let message = e
+                    .downcast_ref::<&str>()
+                    .map(|t| t.to_string())
+                    .or(e.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                error!("{} panicked: {}", req.url, message);
+                Ok(ApiError::Internal("The request could not be processed".to_string()).response())
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::features;
@@ -700,6 +1223,9 @@ mod test {
     use serde_yaml;
     use crate::utils::file_read;
     use crate::vg::VG;
+    use crate::gfa::Gfa;
+    use docopt::Docopt;
+    use std::env;
 
     use self::iron_test::*;
 
@@ -741,5 +1267,275 @@ mod test {
         let result_body2 = response::extract_body_to_bytes(response2);
 
         assert_eq!(result_body2, b"[]");
+
+        let response3 = request::get(
+            "http://localhost:3000/feature?dataset=unknown",
+            Headers::new(),
+            &FeatureHandler::new(conf.clone(), db.clone()),
+        )
+        .unwrap();
+        assert_eq!(response3.status, Some(status::NotFound));
+        let result_body3: serde_json::Value =
+            serde_json::from_str(&response::extract_body_to_string(response3)).unwrap();
+        assert_eq!(result_body3["error"]["code"], "unknown_dataset");
+        assert_eq!(result_body3["error"]["parameter"], "dataset");
+    }
+
+    const TINY_CONFIG: &'static str = r#"
+bin:
+  vg: "vg"
+  vg_tmp: "vg"
+  graphviz: "dot"
+  fa22bit: "faToTwoBit"
+  bigbed: "bedToBigBed"
+reference:
+  chroms: "static/GRCh.json"
+  data:
+    - name: "tiny"
+      features:
+        - name: "genes"
+          url: "test/gencode.v26.chr_patch_hapl_scaff.basic.annotation.wo.header.head.gff3"
+data:
+  - name: "tiny"
+    chr_prefix: "chr"
+    ref_id: "tiny"
+    max_interval: 4
+    source:
+      xg: ""
+      gfa: "test/graph/tiny.gfa"
+      twobit: "test/graph/tiny.2bit"
+    features: []
+    static_files: []
+  - name: "tiny-bed"
+    desc: "The tiny graph with a BED track"
+    chr_prefix: "chr"
+    ref_id: "tiny"
+    max_interval: 10
+    source:
+      xg: ""
+      gfa: "test/graph/tiny.gfa"
+      twobit: "test/graph/tiny.2bit"
+    features:
+      - name: "test"
+        url: "test/bed/test.bed"
+        chr_prefix: "chr"
+    static_files: []
+"#;
+
+    // Routes of `main` on the tiny GFA graph, without vg.
+    fn tiny_router() -> CatchPanic<Router> {
+        let tmp = env::temp_dir()
+            .join(format!("ggbb-handlers-{}", time()))
+            .to_string_lossy()
+            .into_owned();
+        let args: Args = Docopt::new(crate::USAGE)
+            .and_then(|d| {
+                d.argv(vec!["ggbb".to_string(), format!("--tmp={}", tmp)])
+                    .deserialize()
+            })
+            .unwrap();
+        let config: Config = serde_yaml::from_str(TINY_CONFIG).unwrap();
+        let graph = GraphDB::Gfa(Gfa::new());
+        let database = features::tmp_new(graph, &config, format!("{}/rocksdb", tmp), &true);
+        let handlers = Handlers::new(config, args, database).unwrap();
+        let mut router = Router::new();
+        router.get("datasets", handlers.datasets, "datasets");
+        router.get("range/:filename", handlers.ranged_cache, "range");
+        router.get("feature", handlers.feature, "feature");
+        router.get("region", handlers.region, "region");
+        router.get("nodes", handlers.nodes, "nodes");
+        router.get("sequence", handlers.sequence, "sequence");
+        router.get("graph", handlers.graph, "graph");
+        router.get("render/svg", handlers.svg, "svg");
+        router.get("summary", handlers.summary, "summary");
+        router.get("overview", handlers.overview, "overview");
+        router.get("jobs/:id", handlers.jobs, "jobs");
+        router.get(
+            "panic",
+            |_: &mut Request| -> IronResult<Response> { panic!("broken handler") },
+            "panic",
+        );
+        CatchPanic::new(router)
+    }
+
+    #[test]
+    fn handlers_reject_malformed_requests() {
+        let router = tiny_router();
+        let error = |query: &str| -> (Option<status::Status>, serde_json::Value) {
+            let res = request::get(
+                &format!("http://localhost:3000/{}", query),
+                Headers::new(),
+                &router,
+            )
+            .unwrap();
+            let status = res.status;
+            let body = response::extract_body_to_string(res);
+            (
+                status,
+                serde_json::from_str(&body).unwrap_or(serde_json::Value::Null),
+            )
+        };
+        let cases = [
+            ("range/unknown.bb", status::NotFound, "unknown_file"),
+            ("feature", status::BadRequest, "invalid_request"),
+            (
+                "feature?startsWith=a&ref=unknown",
+                status::BadRequest,
+                "invalid_parameter",
+            ),
+            ("feature?equals=NOTAGENE", status::NotFound, "unknown_gene"),
+            (
+                "feature?equals=1&dataset=unknown",
+                status::NotFound,
+                "unknown_dataset",
+            ),
+            ("region?path=chr1", status::BadRequest, "invalid_region"),
+            ("region", status::BadRequest, "missing_parameter"),
+            (
+                "region?path=chr1:0-4&format=wig&bins=2&stat=median",
+                status::BadRequest,
+                "invalid_parameter",
+            ),
+            (
+                "nodes?path=chr1:0-100000000",
+                status::PayloadTooLarge,
+                "region_too_large",
+            ),
+            ("nodes?path=chr1:x-y", status::BadRequest, "invalid_region"),
+            ("sequence?node=abc", status::BadRequest, "invalid_parameter"),
+            ("sequence?node=999", status::NotFound, "unknown_node"),
+            (
+                "sequence?path=chr1:0-100000000",
+                status::PayloadTooLarge,
+                "region_too_large",
+            ),
+            ("graph", status::BadRequest, "missing_parameter"),
+            ("graph?path=chr1", status::BadRequest, "invalid_region"),
+            (
+                "graph?path=chr1:0-100000000",
+                status::PayloadTooLarge,
+                "region_too_large",
+            ),
+            (
+                "graph?path=chr1:0-100000000&downsample=true",
+                status::PayloadTooLarge,
+                "region_too_large",
+            ),
+            (
+                "graph?path=chr1:1-5&uuid=unknown",
+                status::NotFound,
+                "unknown_uuid",
+            ),
+            (
+                "graph?path=chr1:0-4&format=xml",
+                status::BadRequest,
+                "invalid_parameter",
+            ),
+            ("render/svg", status::BadRequest, "missing_parameter"),
+            (
+                "render/svg?path=chr1:0-100000000",
+                status::PayloadTooLarge,
+                "region_too_large",
+            ),
+            ("summary", status::BadRequest, "missing_parameter"),
+            (
+                "summary?path=chr1:0-100000000",
+                status::PayloadTooLarge,
+                "region_too_large",
+            ),
+            (
+                "summary?path=chr1:0-10&resolution=x",
+                status::BadRequest,
+                "invalid_parameter",
+            ),
+            (
+                "overview?source=unknown",
+                status::BadRequest,
+                "invalid_parameter",
+            ),
+            ("jobs/unknown", status::NotFound, "unknown_job"),
+            ("panic", status::InternalServerError, "internal"),
+        ];
+        for &(query, expected, code) in cases.iter() {
+            let (status, body) = error(query);
+            assert_eq!(status, Some(expected), "{}", query);
+            assert_eq!(body["error"]["code"], code, "{}", query);
+        }
+
+        let (status, body) = error("sequence?path=chr1:0-4");
+        assert_eq!(status, Some(status::Ok));
+        assert_eq!(body["sequence"], "ACGT");
+        let (_, body) = error("graph?path=chr1:0-100000000&downsample=true");
+        assert_eq!(
+            body["error"]["message"],
+            "`path` spans 100000000 bp, more than the max interval 400 bp"
+        );
+        let (status, _) = error("graph?path=chr1:0-10");
+        assert_eq!(status, Some(status::PayloadTooLarge));
+        let (status, _) = error("graph?path=chr1:0-10&downsample=true");
+        assert_eq!(status, Some(status::Found));
+        let (status, _) = error("graph?path=chr1:0-4&format=gfa");
+        assert_eq!(status, Some(status::Found));
+        let (status, _) = error("graph?path=chr1:0-4&format=fasta");
+        assert_eq!(status, Some(status::Found));
+        let (status, body) = error("graph?path=chr1:0-10&downsample=true&format=fasta");
+        assert_eq!(status, Some(status::BadRequest));
+        assert_eq!(body["error"]["code"], "unsupported");
+        let (status, body) = error("summary?path=chr1:0-10&resolution=100");
+        assert_eq!(status, Some(status::Ok));
+        assert_eq!(body["resolution"], 100);
+        assert_eq!(body["nodes"][0]["variants"], 1);
+    }
+
+    #[test]
+    fn handlers_route_datasets() {
+        let router = tiny_router();
+        let get = |query: &str| -> (Option<status::Status>, serde_json::Value) {
+            let res = request::get(
+                &format!("http://localhost:3000/{}", query),
+                Headers::new(),
+                &router,
+            )
+            .unwrap();
+            let status = res.status;
+            let body = response::extract_body_to_string(res);
+            (status, serde_json::from_str(&body).unwrap())
+        };
+
+        let (status, body) = get("datasets");
+        assert_eq!(status, Some(status::Ok));
+        assert_eq!(
+            body,
+            json!([
+                {"name": "tiny", "desc": null, "ref_id": "tiny", "chr_prefix": "chr"},
+                {
+                    "name": "tiny-bed",
+                    "desc": "The tiny graph with a BED track",
+                    "ref_id": "tiny",
+                    "chr_prefix": "chr"
+                }
+            ])
+        );
+
+        // The first dataset is the default, and each dataset has its own max interval and index.
+        let (status, _) = get("nodes?path=chr1:0-10");
+        assert_eq!(status, Some(status::PayloadTooLarge));
+        let (status, body) = get("nodes?path=chr1:0-10&dataset=tiny-bed");
+        assert_eq!(status, Some(status::Ok));
+        assert_eq!(body[0]["id"], 1);
+
+        // Only the second dataset has the BED track.
+        let (status, body) = get("region?path=chr1:1500-5100");
+        assert_eq!(status, Some(status::Ok));
+        assert_eq!(body, json!({}));
+        let (status, body) = get("region?path=chr1:1500-5100&dataset=tiny-bed");
+        assert_eq!(status, Some(status::Ok));
+        let starts: Vec<u64> = body["test/bed/test.bed"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["start_offset"].as_u64().unwrap())
+            .collect();
+        assert_eq!(starts, vec![1000, 5000]);
     }
 }
@@ -0,0 +1,258 @@
+use crate::process::{group_command, Pipeline};
+use crate::utils::time;
+use serde_json;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::fs::File;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+// Bytes of the output kept in the log of a job, from its end.
+const MAX_LOG: usize = 65536;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    pub status: JobStatus,
+    pub uuid: String, // The name of the xg the job generates, used as `uuid` on /graph.
+    pub command: Vec<String>,
+    pub log: String,
+    pub created: u64,
+    pub finished: Option<u64>,
+}
+
+struct JobState {
+    jobs: BTreeMap<String, Job>,
+    queue: VecDeque<String>,
+    serial: u64,
+}
+
+// Background jobs, run by a fixed number of workers and killed after `timeout` seconds.
+// Each job is saved as `<dir>/<id>.json` whenever its status changes.
+pub struct Jobs {
+    dir: PathBuf,
+    timeout: u64,
+    state: Mutex<JobState>,
+    available: Condvar,
+}
+
+// The end of the output, cut at a character boundary.
+fn tail(log: &str, max: usize) -> String {
+    let mut start = log.len().saturating_sub(max);
+    while !log.is_char_boundary(start) {
+        start += 1;
+    }
+    log[start..].to_string()
+}
+
+impl Jobs {
+    // Restore jobs saved in the directory. Jobs interrupted by a restart are queued again.
+    pub fn new(dir: &Path, timeout: u64) -> Result<Jobs, Error> {
+        fs::create_dir_all(dir)?;
+        let mut jobs = BTreeMap::new();
+        let mut queue = vec![];
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|t| t.to_str()) != Some("json") {
+                continue;
+            }
+            let mut job: Job = match serde_json::from_reader(File::open(&path)?) {
+                Ok(job) => job,
+                Err(e) => {
+                    debug!("ignoring {:?}: {}", path, e);
+                    continue;
+                }
+            };
+            if job.status == JobStatus::Queued || job.status == JobStatus::Running {
+                job.status = JobStatus::Queued;
+                queue.push(job.id.clone());
+            }
+            jobs.insert(job.id.clone(), job);
+        }
+        queue.sort_by_key(|t| jobs[t].created);
+        Ok(Jobs {
+            dir: dir.to_path_buf(),
+            timeout: timeout,
+            state: Mutex::new(JobState {
+                serial: jobs.len() as u64,
+                jobs: jobs,
+                queue: queue.into_iter().collect(),
+            }),
+            available: Condvar::new(),
+        })
+    }
+
+    fn save(&self, job: &Job) {
+        let path = self.dir.join(format!("{}.json", job.id));
+        let result = serde_json::to_string(job)
+            .map_err(Error::from)
+            .and_then(|json| fs::write(&path, json));
+        if let Err(e) = result {
+            debug!("could not save {:?}: {}", path, e);
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        self.state.lock().unwrap().jobs.get(id).cloned()
+    }
+
+    // Whether a job generating `uuid` is queued or running, so that its files are in use.
+    pub fn is_active(&self, uuid: &str) -> bool {
+        self.state.lock().unwrap().jobs.values().any(|t| {
+            t.uuid == uuid && (t.status == JobStatus::Queued || t.status == JobStatus::Running)
+        })
+    }
+
+    pub fn submit(&self, uuid: String, command: Vec<String>) -> Job {
+        let mut state = self.state.lock().unwrap();
+        state.serial += 1;
+        let job = Job {
+            id: format!("{}-{}", time(), state.serial),
+            status: JobStatus::Queued,
+            uuid: uuid,
+            command: command,
+            log: String::new(),
+            created: time(),
+            finished: None,
+        };
+        self.save(&job);
+        state.queue.push_back(job.id.clone());
+        state.jobs.insert(job.id.clone(), job.clone());
+        self.available.notify_one();
+        job
+    }
+
+    fn update(&self, job: Job) {
+        self.save(&job);
+        self.state.lock().unwrap().jobs.insert(job.id.clone(), job);
+    }
+
+    fn next(&self) -> Job {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(id) = state.queue.pop_front() {
+                if let Some(job) = state.jobs.get_mut(&id) {
+                    job.status = JobStatus::Running;
+                    return job.clone();
+                }
+            }
+            state = self.available.wait(state).unwrap();
+        }
+    }
+
+    fn run(&self, mut job: Job) {
+        self.save(&job);
+        info!("Job {}: {:?}", job.id, job.command);
+        // The script spawns vg by itself, so it is killed with its process group.
+        let mut pipeline = Pipeline::new(self.timeout);
+        let spawned = pipeline
+            .spawn_group(
+                "job",
+                group_command(&job.command[0])
+                    .args(&job.command[1..])
+                    .stdout(Stdio::piped()),
+            )
+            .map(|_| ());
+        let result = spawned.and_then(|_| {
+            pipeline.capture_stdout();
+            pipeline.wait()
+        });
+        match result {
+            Ok(output) => {
+                job.log = tail(&String::from_utf8_lossy(&output), MAX_LOG);
+                job.status = JobStatus::Succeeded;
+            }
+            Err(e) => {
+                job.log = tail(&e.to_string(), MAX_LOG);
+                job.status = JobStatus::Failed;
+            }
+        }
+        job.finished = Some(time());
+        info!("Job {}: {:?}", job.id, job.status);
+        self.update(job);
+    }
+}
+
+// Start `workers` threads, so that at most `workers` jobs run at once.
+pub fn start(jobs: &Arc<Jobs>, workers: usize) {
+    for _ in 0..workers {
+        let jobs = jobs.clone();
+        thread::spawn(move || loop {
+            let job = jobs.next();
+            jobs.run(job);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::time::Duration;
+
+    fn wait(jobs: &Jobs, id: &str) -> Job {
+        for _ in 0..100 {
+            let job = jobs.get(id).unwrap();
+            if job.finished.is_some() {
+                return job;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!("Job {} did not finish", id);
+    }
+
+    #[test]
+    fn tail_works() {
+        assert_eq!(tail("abcdef", 3), "def");
+        assert_eq!(tail("abc", 10), "abc");
+        // "é" is 2 bytes, which is not cut in the middle.
+        assert_eq!(tail("aé", 1), "");
+    }
+
+    #[test]
+    fn jobs_work() {
+        let dir = env::temp_dir().join(format!("ggbb-jobs-{}", time()));
+        let jobs = Arc::new(Jobs::new(&dir, 1).unwrap());
+        let queued = jobs.submit("queued".to_string(), vec!["true".to_string()]);
+        assert_eq!(queued.status, JobStatus::Queued);
+        start(&jobs, 1);
+
+        let job = wait(&jobs, &queued.id);
+        assert_eq!(job.status, JobStatus::Succeeded);
+        assert_eq!(job.uuid, "queued");
+        let args = vec!["sh", "-c", "echo failed >&2; exit 1"];
+        let failed = jobs.submit(
+            "failed".to_string(),
+            args.iter().map(|t| t.to_string()).collect(),
+        );
+        let job = wait(&jobs, &failed.id);
+        assert_eq!(job.status, JobStatus::Failed);
+        assert!(job.log.ends_with("job: failed"), "{}", job.log);
+
+        // A job running longer than the timeout is killed.
+        let args = vec!["sleep", "10"];
+        let slow = jobs.submit(
+            "slow".to_string(),
+            args.iter().map(|t| t.to_string()).collect(),
+        );
+        let job = wait(&jobs, &slow.id);
+        assert_eq!(job.status, JobStatus::Failed);
+        assert!(job.log.contains("timed out"), "{}", job.log);
+
+        let restored = Jobs::new(&dir, 1).unwrap();
+        assert_eq!(restored.get(&slow.id), Some(job));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,13 +1,21 @@
 extern crate serde_yaml;
 
+use crate::bed::BedDB;
 use crate::features::FeatureDB;
+use crate::node_index::NodeIndex;
+use crate::sequence::TwoBit;
+use crate::process::{
+    DEFAULT_CHUNK_TIMEOUT, DEFAULT_CONVERT_TIMEOUT, DEFAULT_DOWNLOAD_TIMEOUT, DEFAULT_FIND_TIMEOUT,
+    DEFAULT_MAX_PROCESSES, DEFAULT_PATHS_TIMEOUT, DEFAULT_RENDER_TIMEOUT,
+};
 use regex::Regex;
 use std::collections::BTreeMap;
 use std::error::Error;
 use std::fmt;
-use crate::vg::GraphDB;
+use std::sync::Mutex;
+use crate::vg::{GraphDB, MAX_INTERVAL};
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct OptionalRegion {
     pub path: String,
     pub start: Option<u64>,
@@ -238,7 +246,9 @@ pub type GeneNameEachReference = BTreeMap<String, GeneNameTree>;
 #[derive(Debug, PartialEq, Serialize)]
 pub struct Database {
     pub features: FeatureDB,
-    pub rocks: String,
+    pub beds: BedDB,
+    pub rocks: BTreeMap<String, NodeIndex>, // Dataset name to its node index.
+    pub twobits: BTreeMap<String, Mutex<TwoBit>>, // Dataset name to its opened `source.twobit`.
     pub gene_name_tree: GeneNameEachReference,
     pub graph: GraphDB,
     pub version: i32,
@@ -252,6 +262,53 @@ pub struct ConfigBin {
     pub graphviz: String,
     pub fa22bit: String,
     pub bigbed: String,
+    pub max_processes: Option<usize>, // vg processes run at once by the server.
+    pub timeout: Option<ConfigTimeout>,
+}
+
+impl ConfigBin {
+    pub fn max_processes(&self) -> usize {
+        self.max_processes.unwrap_or(DEFAULT_MAX_PROCESSES)
+    }
+
+    fn timeout_of<F: Fn(&ConfigTimeout) -> Option<u64>>(&self, f: F, default: u64) -> u64 {
+        self.timeout.as_ref().and_then(f).unwrap_or(default)
+    }
+
+    pub fn find_timeout(&self) -> u64 {
+        self.timeout_of(|t| t.find, DEFAULT_FIND_TIMEOUT)
+    }
+
+    pub fn chunk_timeout(&self) -> u64 {
+        self.timeout_of(|t| t.chunk, DEFAULT_CHUNK_TIMEOUT)
+    }
+
+    pub fn paths_timeout(&self) -> u64 {
+        self.timeout_of(|t| t.paths, DEFAULT_PATHS_TIMEOUT)
+    }
+
+    pub fn download_timeout(&self) -> u64 {
+        self.timeout_of(|t| t.download, DEFAULT_DOWNLOAD_TIMEOUT)
+    }
+
+    pub fn render_timeout(&self) -> u64 {
+        self.timeout_of(|t| t.render, DEFAULT_RENDER_TIMEOUT)
+    }
+
+    pub fn convert_timeout(&self) -> u64 {
+        self.timeout_of(|t| t.convert, DEFAULT_CONVERT_TIMEOUT)
+    }
+}
+
+// Seconds before each kind of subprocess pipeline is killed.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ConfigTimeout {
+    pub find: Option<u64>,     // `vg find`, including the Ruby helper.
+    pub chunk: Option<u64>,    // `vg chunk` with alignments.
+    pub paths: Option<u64>,    // `vg paths`.
+    pub download: Option<u64>, // Downloads of remote xg files.
+    pub render: Option<u64>,   // `bin.graphviz` rendering SVG.
+    pub convert: Option<u64>,  // Conversions of uploaded VCFs into xg.
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -274,7 +331,8 @@ pub struct ConfigSource {
     pub gam: Option<String>,
     pub gamindex: Option<String>,
     pub csv: Option<String>,
-    // pub json: Option<String>,
+    pub json: Option<String>, // Output of `vg view -j`, loaded without vg.
+    pub gfa: Option<String>,
     pub reference: Option<String>,
     // pub ref_id: Option<String>,
     pub twobit: Option<String>,
@@ -298,6 +356,15 @@ pub struct ConfigData {
     pub chr_prefix: String,
     pub features: Vec<ConfigFeature>,
     pub static_files: Vec<ConfigFeature>,
+    pub max_interval: Option<u64>, // The longest region extracted at full resolution.
+}
+
+impl ConfigData {
+    // The max interval of the dataset, or `--interval` if it is not configured.
+    pub fn max_interval(&self, default: &str) -> u64 {
+        self.max_interval
+            .unwrap_or_else(|| default.parse().unwrap_or(MAX_INTERVAL))
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -306,3 +373,13 @@ pub struct Config {
     pub reference: ConfigRef,
     pub data: Vec<ConfigData>,
 }
+
+impl Config {
+    /// Select a dataset by its `name`, or the first entry if no name is given.
+    pub fn dataset(&self, name: Option<&String>) -> Option<&ConfigData> {
+        match name {
+            Some(name) => self.data.iter().find(|data| data.name == *name),
+            None => self.data.first(),
+        }
+    }
+}
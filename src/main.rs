@@ -4,6 +4,7 @@
 
 #[macro_use]
 extern crate serde_derive;
+extern crate serde;
 extern crate docopt;
 #[macro_use]
 extern crate log;
@@ -29,9 +30,23 @@ extern crate staticfile;
 extern crate flate2;
 
 mod annotations;
+mod bed;
+mod cache;
+mod error;
+mod fasta;
 mod features;
+mod gfa;
+mod graph;
 mod handlers;
+mod jobs;
 mod lib;
+mod node_index;
+mod pool;
+mod process;
+mod render;
+mod sankey;
+mod sequence;
+mod summary;
 mod utils;
 mod vg;
 
@@ -49,6 +64,8 @@ use std::path::Path;
 use std::process;
 
 use docopt::Docopt;
+use crate::gfa::Gfa;
+use crate::graph::Native;
 use crate::vg::VG;
 
 const USAGE: &'static str = "
@@ -64,19 +81,27 @@ Options:
   --version  Show version.
   --config=<yaml>  Config file [default: config.yaml].
   --http=<hostport>  Host and Port [default: 127.0.0.1:8081].
-  --threads=<thread>  Threads per process [default: 1].
+  --threads=<thread>  HTTP workers [default: 8].
+  --vg-threads=<threads>  Threads of each vg process [default: 1].
+  --heavy=<heavy>  Graph generations run at once; as many more wait in a queue [default: 2].
   --tmp=<tmp>  Cache folder [default: ./tmp].
+  --tmp-size=<bytes>  Max bytes kept in the cache folder [default: 10000000000].
+  --tmp-ttl=<secs>  Seconds cached files are kept after the last access, 0 for ever [default: 604800].
   --static=<static>  Static folder [default: ./static].
   --rocksdb=<rocksdb>  Path for rocksdb storage [default: ./rocksdb].
   --build=<build>  Path for client html [default: ./build].
   --api=<api>  URL for api [default: /api/v1/].
-  --interval=<interval>  Max interval on paths [default: 50000].
+  --interval=<interval>  Max interval on paths, unless a dataset sets `max_interval` [default: 50000].
+  --jobs=<jobs>  Max number of upload conversions run at once [default: 2].
+  --list=<list>  Regions or gene names to cache with --cache [default: cache.txt].
+  --steps=<steps>  Steps of graphs cached with --cache [default: 2].
   -c --cache  Cache a given coordinate list.
   -s --serve  Serve client html.
   -r --reinitrocks  Reinitialize rocks db if true.
   -n --notest  Do not run tests whether vg works.
   -i --onlyinit  Initialize and exit.
   -u --upload  Permit upload option.
+  --admin  Serve cache statistics at admin/cache.
   -v --verbose  Force verbose.
 ";
 
@@ -89,10 +114,18 @@ pub struct Args {
     flag_reinitrocks: bool,
     flag_cache: bool,
     flag_upload: bool,
+    flag_admin: bool,
     flag_config: String,
     flag_http: String,
     flag_tmp: String,
+    flag_tmp_size: u64,
+    flag_tmp_ttl: u64,
     flag_threads: i32,
+    flag_vg_threads: i32,
+    flag_heavy: usize,
+    flag_jobs: usize,
+    flag_list: String,
+    flag_steps: i64,
     flag_static: String,
     flag_rocksdb: String,
     flag_build: String,
@@ -121,15 +154,43 @@ fn main() {
         Ok(conf) => conf,
     };
     let vg_inner = VG {};
+    let data = &deserialized_config.data;
+    let vg = if data.iter().all(|data| data.source.gfa.is_some()) {
+        GraphDB::Gfa(Gfa::new())
+    } else if data.iter().all(|data| data.source.json.is_some()) {
+        GraphDB::Native(Native::new())
+    } else if data
+        .iter()
+        .any(|data| data.source.gfa.is_some() || data.source.json.is_some())
+    {
+        // One backend serves every dataset, so they must all share gfa, json or neither.
+        let sources: Vec<String> = data
+            .iter()
+            .map(|data| {
+                let source = match (&data.source.gfa, &data.source.json) {
+                    (&Some(_), _) => "gfa",
+                    (&None, &Some(_)) => "json",
+                    (&None, &None) => "xg",
+                };
+                format!("{}: {}", data.name, source)
+            })
+            .collect();
+        println!(
+            "datasets mix graph backends ({}); set gfa on all of them, json on all of them or neither",
+            sources.join(", ")
+        );
+        process::exit(1);
+    } else {
+        GraphDB::VG(vg_inner)
+    };
 
-    if !args.flag_notest {
-        if !vg_inner.test(&deserialized_config) {
+    if let GraphDB::VG(ref vg_inner) = vg {
+        if !args.flag_notest && !vg_inner.test(&deserialized_config) {
             return;
         }
     }
 
     let db = &args.flag_rocksdb.clone();
-    let vg = GraphDB::VG(vg_inner);
     let database = tmp_new(vg, &deserialized_config, db.clone(), &args.flag_reinitrocks);
     let static_str = &args.flag_static.clone();
     let static_path = Path::new(static_str);
@@ -138,29 +199,65 @@ fn main() {
     let build_str = &args.flag_build.clone();
     let build_path = Path::new(build_str);
     let flag_serve = &args.flag_serve.clone();
-    let flag_upload = &args.flag_serve.clone();
+    let flag_upload = &args.flag_upload.clone();
+    let flag_admin = args.flag_admin;
 
     if args.flag_onlyinit {
         println!("Initialization completion");
         process::exit(0);
     }
 
+    if args.flag_cache {
+        match cache::precompute(&deserialized_config, &database, &args) {
+            Ok(0) => process::exit(0),
+            Ok(failures) => {
+                println!("{} graphs could not be cached", failures);
+                process::exit(1);
+            }
+            Err(e) => {
+                println!("couldn't read {}: {}", args.flag_list, e);
+                process::exit(1);
+            }
+        }
+    }
+
     let api = &args.flag_api.clone();
-    let handlers = Handlers::new(deserialized_config, args, database);
+    let threads = args.flag_threads.max(1) as usize;
+    if threads <= 2 * args.flag_heavy {
+        println!(
+            "--threads should be more than twice --heavy, or graph generations can occupy every HTTP worker"
+        );
+    }
+    let handlers = match Handlers::new(deserialized_config, args, database) {
+        Ok(handlers) => handlers,
+        Err(e) => {
+            println!("couldn't open the job directory under --tmp: {}", e);
+            process::exit(1);
+        }
+    };
     let json_content_middleware = JsonAfterMiddleware;
 
     let mut router = Router::new();
+    router.get("datasets", handlers.datasets, "datasets");
     router.get("range/:filename", handlers.ranged_cache, "range");
     router.get("feature", handlers.feature, "feature");
     router.get("region", handlers.region, "region");
+    router.get("nodes", handlers.nodes, "nodes");
+    router.get("sequence", handlers.sequence, "sequence");
     router.get("graph", handlers.graph, "graph");
+    router.get("render/svg", handlers.svg, "svg");
+    router.get("summary", handlers.summary, "summary");
     router.get("overview", handlers.overview, "overview");
     router.post("render", handlers.upload, "fetch");
+    if flag_admin {
+        router.get("admin/cache", handlers.cache_stats, "cache_stats");
+    }
     if *flag_upload {
         router.post("upload", handlers.multi_part, "multi");
+        router.get("jobs/:id", handlers.jobs, "jobs");
     }
 
-    let mut chain = Chain::new(router);
+    let mut chain = Chain::new(CatchPanic::new(router));
     //chain.link_before(logger_before); // Should be first!
     chain.link_after(json_content_middleware);
     //chain.link_after(logger_after); // Should be last!
@@ -177,6 +274,8 @@ fn main() {
     chain2.link_before(logger_before); // Should be first!
     chain2.link_after(logger_after); // Should be last!
 
-    println!("Start server on {}", http);
-    Iron::new(chain2).http(http).unwrap();
+    println!("Start server on {} with {} threads", http, threads);
+    let mut iron = Iron::new(chain2);
+    iron.threads = threads;
+    iron.http(http).unwrap();
 }
@@ -0,0 +1,281 @@
+use crate::lib::Region;
+use rocks::rocksdb::*;
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt;
+use std::mem::transmute;
+
+// The column family keyed by (path, start), in addition to the default one keyed by node id.
+const COORDINATE: &'static str = "coordinate";
+
+fn descriptors() -> Vec<ColumnFamilyDescriptor> {
+    vec![
+        ColumnFamilyDescriptor::new(DEFAULT_COLUMN_FAMILY_NAME, ColumnFamilyOptions::default()),
+        ColumnFamilyDescriptor::new(COORDINATE, ColumnFamilyOptions::default()),
+    ]
+}
+
+// Paths are terminated by NUL, and starts are big endian, so that keys sort by (path, start).
+fn coordinate_key(path: &str, start: u64) -> Vec<u8> {
+    let mut key = path.as_bytes().to_vec();
+    key.push(0);
+    key.extend_from_slice(&start.to_be_bytes());
+    key
+}
+
+fn parse_coordinate_key(key: &[u8]) -> Option<(&[u8], u64)> {
+    if key.len() < 9 {
+        return None;
+    }
+    let (path, start) = key.split_at(key.len() - 9);
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&start[1..]);
+    Some((path, u64::from_be_bytes(bytes)))
+}
+
+// The value on the coordinate column family is "<node id>\t<stop>".
+fn parse_coordinate_value(value: &[u8]) -> Option<(u64, u64)> {
+    let value = String::from_utf8_lossy(value);
+    let mut items = value.split("\t").map(|t| t.parse::<u64>());
+    match (items.next(), items.next()) {
+        (Some(Ok(id)), Some(Ok(stop))) => Some((id, stop)),
+        _ => None,
+    }
+}
+
+// Builds both directions of the index.
+pub struct NodeIndexWriter {
+    db: DB,
+    coordinate: ColumnFamily,
+}
+
+impl NodeIndexWriter {
+    pub fn create(path: &String) -> Result<NodeIndexWriter, Status> {
+        let options = Options::default().map_db_options(|db| {
+            db.create_if_missing(true)
+                .create_missing_column_families(true)
+        });
+        let (db, mut cfs) = DB::open_with_column_families(&options, path, descriptors())?;
+        Ok(NodeIndexWriter {
+            db: db,
+            coordinate: cfs.remove(1),
+        })
+    }
+
+    pub fn put(&self, node_id: u64, region: &Region) -> Result<(), Status> {
+        let raw_bytes: [u8; 8] = unsafe { transmute(node_id) };
+        self.db.put(
+            &WriteOptions::default(),
+            &raw_bytes,
+            region.uuid().as_bytes(),
+        )?;
+        self.put_coordinate(node_id, region)
+    }
+
+    // Only the coordinate direction, for nodes which keep the region of another path.
+    pub fn put_coordinate(&self, node_id: u64, region: &Region) -> Result<(), Status> {
+        self.db.put_cf(
+            &WriteOptions::default(),
+            &self.coordinate,
+            &coordinate_key(&region.path, region.start),
+            format!("{}\t{}", node_id, region.stop).as_bytes(),
+        )
+    }
+}
+
+// The node index of a dataset, opened once in read-only mode.
+// Read-only handles take no lock, so that several server processes can share one index.
+pub struct NodeIndex {
+    path: String,
+    db: Option<DB>,
+    coordinate: Option<ColumnFamily>,
+}
+
+impl NodeIndex {
+    pub fn open(path: &String) -> NodeIndex {
+        let options = Options::default();
+        let (db, coordinate) = match DB::open_for_readonly_with_column_families(
+            &options,
+            path,
+            descriptors(),
+            false,
+        ) {
+            Ok((db, mut cfs)) => (Some(db), Some(cfs.remove(1))),
+            // Indexes built before the coordinate column family only support node lookups.
+            Err(_) => match DB::open_for_readonly(&options, path, false) {
+                Ok(db) => {
+                    info!(
+                        "{} has no coordinate index; rebuild it with --reinitrocks",
+                        path
+                    );
+                    (Some(db), None)
+                }
+                Err(e) => {
+                    debug!("could not open {}: {:?}", path, e);
+                    (None, None)
+                }
+            },
+        };
+        NodeIndex {
+            path: path.clone(),
+            db: db,
+            coordinate: coordinate,
+        }
+    }
+
+    pub fn path(&self) -> &String {
+        &self.path
+    }
+
+    pub fn get(&self, node_id: u64) -> Option<Region> {
+        let raw_bytes: [u8; 8] = unsafe { transmute(node_id) };
+        let coord_raw = self
+            .db
+            .as_ref()?
+            .get(&ReadOptions::default(), &raw_bytes)
+            .ok()?;
+        String::from_utf8(coord_raw.to_vec())
+            .ok()
+            .and_then(|t| Region::new(t).ok())
+    }
+
+    // Look up many nodes in one call; missing nodes are left out.
+    pub fn multi_get(&self, node_ids: &[u64]) -> HashMap<u64, Region> {
+        let mut regions = HashMap::new();
+        let db = match self.db {
+            Some(ref db) => db,
+            None => return regions,
+        };
+        let keys: Vec<[u8; 8]> = node_ids
+            .iter()
+            .map(|&t| unsafe { transmute::<u64, [u8; 8]>(t) })
+            .collect();
+        let key_refs: Vec<&[u8]> = keys.iter().map(|t| &t[..]).collect();
+        let values = db.multi_get(&ReadOptions::default(), &key_refs);
+        for (&node_id, value) in node_ids.iter().zip(values.into_iter()) {
+            if let Ok(coord_raw) = value {
+                if let Ok(Ok(coord)) = String::from_utf8(coord_raw.to_vec()).map(Region::new) {
+                    regions.insert(node_id, coord);
+                }
+            }
+        }
+        regions
+    }
+
+    // Whether nodes can be looked up by coordinates, which older indexes cannot.
+    pub fn has_coordinates(&self) -> bool {
+        self.coordinate.is_some()
+    }
+
+    // Nodes overlapping the region, ordered by their start on the path.
+    pub fn nodes(&self, region: &Region) -> Vec<(u64, Region)> {
+        let mut nodes = vec![];
+        let (db, coordinate) = match (&self.db, &self.coordinate) {
+            (&Some(ref db), &Some(ref coordinate)) => (db, coordinate),
+            _ => return nodes,
+        };
+        let path = region.path.as_bytes();
+        let mut iter = db.new_iterator_cf(&ReadOptions::default(), coordinate);
+        // The node covering the start begins at or before it.
+        iter.seek_for_prev(&coordinate_key(&region.path, region.start));
+        if !iter.is_valid() {
+            iter.seek_to_first();
+        }
+        while iter.is_valid() {
+            if let Some((key_path, start)) = parse_coordinate_key(iter.key()) {
+                if key_path > path || (key_path == path && start >= region.stop) {
+                    break;
+                }
+                if key_path == path {
+                    if let Some((id, stop)) = parse_coordinate_value(iter.value()) {
+                        if stop > region.start {
+                            nodes.push((
+                                id,
+                                Region {
+                                    path: region.path.clone(),
+                                    start: start,
+                                    stop: stop,
+                                },
+                            ));
+                        }
+                    }
+                }
+            }
+            iter.next();
+        }
+        nodes
+    }
+}
+
+impl fmt::Debug for NodeIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NodeIndex({})", self.path)
+    }
+}
+
+impl PartialEq for NodeIndex {
+    fn eq(&self, other: &NodeIndex) -> bool {
+        self.path == other.path
+    }
+}
+
+impl Serialize for NodeIndex {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::time;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn node_index_works() {
+        let path = env::temp_dir()
+            .join(format!("ggbb-node-index-{}", time()))
+            .to_string_lossy()
+            .into_owned();
+        {
+            let writer = NodeIndexWriter::create(&path).unwrap();
+            for &(id, coord) in [(1u64, "1:0-4"), (2, "1:4-5"), (4, "1:5-9"), (7, "10:0-3")].iter()
+            {
+                writer
+                    .put(id, &Region::new(coord.to_string()).unwrap())
+                    .unwrap();
+            }
+            writer
+                .put_coordinate(4, &Region::new("HG002:0-4".to_string()).unwrap())
+                .unwrap();
+        }
+        let index = NodeIndex::open(&path);
+        assert!(index.has_coordinates());
+        assert!(!NodeIndex::open(&format!("{}.missing", path)).has_coordinates());
+        // Another read-only handle on the same index does not conflict.
+        let other = NodeIndex::open(&path);
+        assert_eq!(index.get(1), Region::new("1:0-4".to_string()).ok());
+        assert_eq!(other.get(3), None);
+        let regions = index.multi_get(&[2, 3, 1]);
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[&2].start, 4);
+
+        let ids = |coord: &str| -> Vec<u64> {
+            index
+                .nodes(&Region::new(coord.to_string()).unwrap())
+                .iter()
+                .map(|t| t.0)
+                .collect()
+        };
+        assert_eq!(ids("1:2-6"), vec![1, 2, 4]);
+        assert_eq!(ids("1:4-5"), vec![2]);
+        assert_eq!(ids("1:9-20"), Vec::<u64>::new());
+        assert_eq!(ids("10:0-1"), vec![7]);
+        // A node on another path is found there, but keeps its first region.
+        assert_eq!(ids("HG002:0-1"), vec![4]);
+        assert_eq!(index.get(4), Region::new("1:5-9".to_string()).ok());
+        assert_eq!(ids("2:0-100"), Vec::<u64>::new());
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
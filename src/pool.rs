@@ -0,0 +1,92 @@
+use std::io::{Error, ErrorKind};
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::mpsc::{channel, sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+type Task = Box<dyn FnOnce() + Send>;
+
+// A fixed number of workers for heavy requests, with a bounded queue.
+// Requests beyond the queue are refused, so that they never occupy every HTTP worker.
+pub struct WorkPool {
+    sender: Mutex<SyncSender<Task>>,
+}
+
+impl WorkPool {
+    pub fn new(workers: usize, queue: usize) -> WorkPool {
+        let (sender, receiver) = sync_channel::<Task>(queue);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..workers.max(1) {
+            let receiver = receiver.clone();
+            thread::spawn(move || loop {
+                let task = match receiver.lock().unwrap().recv() {
+                    Ok(task) => task,
+                    Err(_) => return,
+                };
+                // A panicking task does not take the worker with it.
+                let _ = panic::catch_unwind(AssertUnwindSafe(task));
+            });
+        }
+        WorkPool {
+            sender: Mutex::new(sender),
+        }
+    }
+
+    // Run `f` on a worker and wait for its result.
+    pub fn run<F, T>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (result_sender, result): (_, Receiver<T>) = channel();
+        let task: Task = Box::new(move || {
+            let _ = result_sender.send(f());
+        });
+        match self.sender.lock().unwrap().try_send(task) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                return Err(Error::new(
+                    ErrorKind::WouldBlock,
+                    "The server is busy; retry later",
+                ))
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                return Err(Error::new(ErrorKind::Other, "The workers have stopped"))
+            }
+        }
+        result
+            .recv()
+            .map_err(|_| Error::new(ErrorKind::Other, "The task panicked"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn work_pool_works() {
+        let pool = Arc::new(WorkPool::new(1, 1));
+        assert_eq!(pool.run(|| 1 + 1).unwrap(), 2);
+        assert!(pool.run(|| panic!("failed")).is_err());
+
+        // One task runs, one waits in the queue, and the third is refused.
+        let threads: Vec<_> = (0..2)
+            .map(|_| {
+                let pool = pool.clone();
+                let t =
+                    thread::spawn(move || pool.run(|| thread::sleep(Duration::from_millis(500))));
+                thread::sleep(Duration::from_millis(100));
+                t
+            })
+            .collect();
+        let refused = pool.run(|| ()).unwrap_err();
+        assert_eq!(refused.kind(), ErrorKind::WouldBlock);
+        for t in threads.into_iter() {
+            assert!(t.join().unwrap().is_ok());
+        }
+        assert!(pool.run(|| ()).is_ok());
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_MAX_PROCESSES: usize = 8;
+pub const DEFAULT_FIND_TIMEOUT: u64 = 60;
+pub const DEFAULT_CHUNK_TIMEOUT: u64 = 300;
+pub const DEFAULT_PATHS_TIMEOUT: u64 = 60;
+pub const DEFAULT_DOWNLOAD_TIMEOUT: u64 = 100;
+pub const DEFAULT_RENDER_TIMEOUT: u64 = 60;
+pub const DEFAULT_CONVERT_TIMEOUT: u64 = 3600;
+const POLL_INTERVAL: u64 = 50;
+
+struct SlotState {
+    running: usize,         // The number of vg processes running in this server.
+    waiting: VecDeque<u64>, // Tickets of the waiters, served first come first served.
+    serial: u64,
+}
+
+// Capped by `bin.max_processes`.
+static SLOTS: Mutex<SlotState> = Mutex::new(SlotState {
+    running: 0,
+    waiting: VecDeque::new(),
+    serial: 0,
+});
+static RELEASED: Condvar = Condvar::new();
+
+// Slots for vg processes, released on drop.
+pub struct Slots {
+    count: usize,
+}
+
+impl Slots {
+    // Block until `count` more vg processes may run, for `timeout` seconds at most.
+    // Waiters are served in order, so that a long pipeline is not starved by short ones.
+    // The count is clipped to the cap, so that a pipeline longer than the cap still runs alone.
+    pub fn acquire(count: usize, max: usize, timeout: u64) -> Result<Slots, Error> {
+        let max = max.max(1);
+        let count = count.min(max);
+        let deadline = Instant::now() + Duration::from_secs(timeout);
+        let mut state = SLOTS.lock().unwrap();
+        state.serial += 1;
+        let ticket = state.serial;
+        state.waiting.push_back(ticket);
+        loop {
+            if state.waiting.front() == Some(&ticket) && state.running + count <= max {
+                state.waiting.pop_front();
+                state.running += count;
+                // The next waiter may fit as well.
+                RELEASED.notify_all();
+                return Ok(Slots { count: count });
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                state.waiting.retain(|&t| t != ticket);
+                RELEASED.notify_all();
+                return Err(Error::new(
+                    ErrorKind::TimedOut,
+                    format!("No vg process could be started for {} seconds", timeout),
+                ));
+            }
+            state = RELEASED.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+}
+
+impl Drop for Slots {
+    fn drop(&mut self) {
+        SLOTS.lock().unwrap().running -= self.count;
+        RELEASED.notify_all();
+    }
+}
+
+pub fn running() -> usize {
+    SLOTS.lock().unwrap().running
+}
+
+// A command to run `program` as the leader of a new process group, e.g. the Ruby helper
+// which spawns vg by itself.
+pub fn group_command(program: &str) -> Command {
+    let mut command = Command::new("setsid");
+    command.arg(program);
+    command
+}
+
+fn read_all<R: Read + Send + 'static>(mut reader: R) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = vec![];
+        let _ = reader.read_to_end(&mut buf);
+        buf
+    })
+}
+
+struct Stage {
+    name: String,
+    child: Child,
+    stderr: Option<JoinHandle<Vec<u8>>>,
+    group: bool,
+}
+
+impl Stage {
+    fn kill(&mut self) {
+        if self.group {
+            // `setsid` runs the process as the leader of a new process group.
+            let _ = Command::new("kill")
+                .args(&["-KILL", "--", &format!("-{}", self.child.id())])
+                .status();
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// Processes connected by pipes, which are waited for and killed together.
+// Stderr of each process is captured, and reported when the pipeline fails.
+pub struct Pipeline {
+    stages: Vec<Stage>,
+    stdout: Option<JoinHandle<Vec<u8>>>,
+    stdin: Option<JoinHandle<Result<(), Error>>>,
+    timeout: Duration,
+    _slots: Option<Slots>,
+}
+
+impl Pipeline {
+    pub fn new(timeout: u64) -> Pipeline {
+        Pipeline {
+            stages: vec![],
+            stdout: None,
+            stdin: None,
+            timeout: Duration::from_secs(timeout),
+            _slots: None,
+        }
+    }
+
+    // Take `vg_count` slots of the global cap before spawning vg processes,
+    // waiting for them no longer than the timeout.
+    pub fn with_slots(
+        timeout: u64,
+        vg_count: usize,
+        max_processes: usize,
+    ) -> Result<Pipeline, Error> {
+        let mut pipeline = Pipeline::new(timeout);
+        pipeline._slots = Some(Slots::acquire(vg_count, max_processes, timeout)?);
+        Ok(pipeline)
+    }
+
+    pub fn spawn(&mut self, name: &str, command: &mut Command) -> Result<&mut Child, Error> {
+        self.spawn_stage(name, command, false)
+    }
+
+    // Spawn a command made by `group_command`, whose descendants are killed with it.
+    pub fn spawn_group(&mut self, name: &str, command: &mut Command) -> Result<&mut Child, Error> {
+        self.spawn_stage(name, command, true)
+    }
+
+    fn spawn_stage(
+        &mut self,
+        name: &str,
+        command: &mut Command,
+        group: bool,
+    ) -> Result<&mut Child, Error> {
+        let mut child = match command.stderr(Stdio::piped()).spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                self.kill();
+                return Err(Error::new(
+                    e.kind(),
+                    format!("{} could not be started: {}", name, e),
+                ));
+            }
+        };
+        let stderr = child.stderr.take().map(read_all);
+        self.stages.push(Stage {
+            name: name.to_string(),
+            child: child,
+            stderr: stderr,
+            group: group,
+        });
+        Ok(&mut self.stages.last_mut().unwrap().child)
+    }
+
+    // Feed `input` to the last process from another thread, so that it never blocks on its stdout.
+    pub fn write_stdin(&mut self, input: Vec<u8>) {
+        let stdin: Option<ChildStdin> = self.stages.last_mut().and_then(|t| t.child.stdin.take());
+        if let Some(mut stdin) = stdin {
+            self.stdin = Some(thread::spawn(move || stdin.write_all(&input)));
+        }
+    }
+
+    // Stdout of the last process, spawned with a piped stdout, to be connected to the next one.
+    pub fn take_stdout(&mut self) -> Result<ChildStdout, Error> {
+        self.stages
+            .last_mut()
+            .and_then(|t| t.child.stdout.take())
+            .ok_or(Error::new(ErrorKind::BrokenPipe, "No stdout to pipe"))
+    }
+
+    // Collect stdout of the last process, which must have been spawned with a piped stdout.
+    pub fn capture_stdout(&mut self) {
+        let stdout = self.stages.last_mut().and_then(|t| t.child.stdout.take());
+        self.stdout = stdout.map(read_all);
+    }
+
+    fn kill(&mut self) {
+        for stage in self.stages.iter_mut() {
+            stage.kill();
+        }
+    }
+
+    fn stderr(&mut self) -> String {
+        self.stages
+            .iter_mut()
+            .filter_map(|t| {
+                let stderr = t.stderr.take()?.join().ok()?;
+                let stderr = String::from_utf8_lossy(&stderr).trim().to_string();
+                match stderr.is_empty() {
+                    true => None,
+                    false => Some(format!("{}: {}", t.name, stderr)),
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    // Wait for every process until the timeout, and return stdout captured by `capture_stdout`.
+    // All processes are killed if any of them fails or the timeout is reached.
+    pub fn wait(mut self) -> Result<Vec<u8>, Error> {
+        let deadline = Instant::now() + self.timeout;
+        let mut finished = vec![false; self.stages.len()];
+        while finished.iter().any(|t| !t) {
+            let mut failed = None;
+            for (i, stage) in self.stages.iter_mut().enumerate() {
+                if finished[i] {
+                    continue;
+                }
+                if let Some(status) = stage.child.try_wait()? {
+                    finished[i] = true;
+                    if !status.success() {
+                        failed = Some(format!("{} failed with {}", stage.name, status));
+                        break;
+                    }
+                }
+            }
+            if let Some(message) = failed {
+                self.kill();
+                let stderr = self.stderr();
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("{}\n{}", message, stderr),
+                ));
+            }
+            if Instant::now() > deadline {
+                self.kill();
+                let stderr = self.stderr();
+                return Err(Error::new(
+                    ErrorKind::TimedOut,
+                    format!(
+                        "{} timed out after {} seconds\n{}",
+                        self.stages
+                            .iter()
+                            .map(|t| t.name.as_str())
+                            .collect::<Vec<&str>>()
+                            .join(" | "),
+                        self.timeout.as_secs(),
+                        stderr
+                    ),
+                ));
+            }
+            if finished.iter().any(|t| !t) {
+                thread::sleep(Duration::from_millis(POLL_INTERVAL));
+            }
+        }
+        if let Some(stdin) = self.stdin.take() {
+            // A process may exit without reading all of its input.
+            if let Err(e) = stdin.join().unwrap_or(Ok(())) {
+                debug!("Could not write to stdin: {}", e);
+            }
+        }
+        let _ = self.stderr();
+        Ok(self
+            .stdout
+            .take()
+            .and_then(|t| t.join().ok())
+            .unwrap_or(vec![]))
+    }
+}
+
+// Processes left behind by an early return are not leaked.
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        for stage in self.stages.iter_mut() {
+            if let Ok(None) = stage.child.try_wait() {
+                stage.kill();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipeline_works() {
+        let mut pipeline = Pipeline::new(10);
+        let stdout = pipeline
+            .spawn(
+                "echo",
+                Command::new("echo").arg("ACGT").stdout(Stdio::piped()),
+            )
+            .unwrap()
+            .stdout
+            .take()
+            .unwrap();
+        pipeline
+            .spawn(
+                "tr",
+                Command::new("tr")
+                    .args(&["A", "T"])
+                    .stdin(Stdio::from(stdout))
+                    .stdout(Stdio::piped()),
+            )
+            .unwrap();
+        pipeline.capture_stdout();
+        assert_eq!(pipeline.wait().unwrap(), b"TCGT\n".to_vec());
+
+        let mut pipeline = Pipeline::new(10);
+        pipeline
+            .spawn(
+                "sh",
+                Command::new("sh").args(&["-c", "echo broken >&2; exit 3"]),
+            )
+            .unwrap();
+        let e = pipeline.wait().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Other);
+        assert!(e.to_string().contains("sh: broken"));
+    }
+
+    #[test]
+    fn pipeline_times_out() {
+        let start = Instant::now();
+        let mut pipeline = Pipeline::new(1);
+        let stdout = pipeline
+            .spawn(
+                "sleep",
+                Command::new("sleep").arg("30").stdout(Stdio::piped()),
+            )
+            .unwrap()
+            .stdout
+            .take()
+            .unwrap();
+        // The shell leaves its own child, which holds stderr open unless it is killed too.
+        pipeline
+            .spawn_group(
+                "sh",
+                group_command("sh")
+                    .args(&["-c", "sleep 30; cat"])
+                    .stdin(Stdio::from(stdout)),
+            )
+            .unwrap();
+        let e = pipeline.wait().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn slots_work() {
+        let slots = Slots::acquire(3, 2, 10).unwrap();
+        assert!(running() >= 2);
+        drop(slots);
+
+        // A waiter for one slot does not overtake an earlier waiter for two.
+        let one = Slots::acquire(1, 2, 10).unwrap();
+        let two = thread::spawn(|| Slots::acquire(2, 2, 10).map(|_| ()));
+        thread::sleep(Duration::from_millis(200));
+        let e = Slots::acquire(1, 2, 1).map(|_| ()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::TimedOut);
+        drop(one);
+        assert!(two.join().unwrap().is_ok());
+    }
+}
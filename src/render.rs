@@ -0,0 +1,147 @@
+use crate::graph::VgGraph;
+use crate::lib::Config;
+use crate::process::Pipeline;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::io::Error;
+use std::process::{Command, Stdio};
+
+const REFERENCE_COLOR: &'static str = "#000000";
+const REFERENCE_FILL: &'static str = "#ffe08a";
+const OFF_PATH_COLOR: &'static str = "#bbbbbb";
+// Colours of the other paths, in the order they appear in the graph.
+const PALETTE: [&'static str; 10] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+    "#bcbd22", "#17becf",
+];
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// The DOT description of the graph, where nodes are sized by their sequence length and
+// edges are coloured by the paths through them. The `reference` path is drawn thick in black.
+pub fn to_dot(graph: &VgGraph, reference: &str) -> String {
+    let mut colors: HashMap<&str, &str> = HashMap::new();
+    for path in graph.path.iter() {
+        if colors.contains_key(path.name.as_str()) {
+            continue;
+        }
+        let color = match path.name == reference {
+            true => REFERENCE_COLOR,
+            false => {
+                let others = colors.values().filter(|&&t| t != REFERENCE_COLOR).count();
+                PALETTE[others % PALETTE.len()]
+            }
+        };
+        colors.insert(&path.name, color);
+    }
+    // Paths through each edge, whichever direction it is traversed in.
+    let mut edge_paths: HashMap<(u64, u64), Vec<&str>> = HashMap::new();
+    let mut on_reference: HashSet<u64> = HashSet::new();
+    for path in graph.path.iter() {
+        for mapping in path.mapping.iter() {
+            if path.name == reference {
+                on_reference.insert(mapping.position.node_id);
+            }
+        }
+        for pair in path.mapping.windows(2) {
+            let (a, b) = (pair[0].position.node_id, pair[1].position.node_id);
+            let names = edge_paths.entry((a.min(b), a.max(b))).or_insert(vec![]);
+            if !names.contains(&path.name.as_str()) {
+                names.push(&path.name);
+            }
+        }
+    }
+
+    let mut dot = String::new();
+    let _ = writeln!(dot, "digraph G {{");
+    let _ = writeln!(dot, "  rankdir=LR;");
+    let _ = writeln!(
+        dot,
+        "  node [shape=box, style=filled, fillcolor=\"#ffffff\", fontname=\"Helvetica\", fontsize=10];"
+    );
+    for node in graph.node.iter() {
+        let length = node.length();
+        let width = 0.3 + ((length + 1) as f64).log2() / 4.0;
+        let fill = match on_reference.contains(&node.id) {
+            true => format!(", fillcolor=\"{}\", penwidth=2", REFERENCE_FILL),
+            false => "".to_string(),
+        };
+        let _ = writeln!(
+            dot,
+            "  \"{}\" [label=\"{}\\n{} bp\", width={:.2}{}];",
+            node.id, node.id, length, width, fill
+        );
+    }
+    for edge in graph.edge.iter() {
+        let key = (edge.from.min(edge.to), edge.from.max(edge.to));
+        let (color, penwidth, tooltip) = match edge_paths.get(&key) {
+            Some(names) => (
+                names
+                    .iter()
+                    .map(|t| colors.get(t).cloned().unwrap_or(OFF_PATH_COLOR))
+                    .collect::<Vec<&str>>()
+                    .join(":"),
+                if names.contains(&reference) { 3 } else { 1 },
+                names.join(", "),
+            ),
+            None => (OFF_PATH_COLOR.to_string(), 1, "".to_string()),
+        };
+        let _ = writeln!(
+            dot,
+            "  \"{}\" -> \"{}\" [color=\"{}\", penwidth={}, tooltip=\"{}\"];",
+            edge.from,
+            edge.to,
+            color,
+            penwidth,
+            escape(&tooltip)
+        );
+    }
+    let _ = writeln!(dot, "}}");
+    dot
+}
+
+// Render a DOT description into SVG with `bin.graphviz`.
+pub fn render_svg(config: &Config, dot: &str) -> Result<Vec<u8>, Error> {
+    let commands: Vec<&str> = config.bin.graphviz.split(" ").collect();
+    let mut pipeline = Pipeline::new(config.bin.render_timeout());
+    pipeline.spawn(
+        "graphviz",
+        Command::new(commands[0])
+            .args(&commands[1..])
+            .arg("-Tsvg")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped()),
+    )?;
+    pipeline.write_stdin(dot.as_bytes().to_vec());
+    pipeline.capture_stdout();
+    pipeline.wait()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::tiny_subgraph;
+
+    #[test]
+    fn to_dot_works() {
+        let dot = to_dot(&tiny_subgraph(), "chr1");
+        let lines: Vec<&str> = dot.lines().map(|t| t.trim()).collect();
+        assert_eq!(lines[0], "digraph G {");
+        assert!(lines.contains(
+            &"\"1\" [label=\"1\\n4 bp\", width=0.88, fillcolor=\"#ffe08a\", penwidth=2];"
+        ));
+        assert!(lines.contains(&"\"3\" [label=\"3\\n1 bp\", width=0.55];"));
+        assert!(
+            lines.contains(&"\"1\" -> \"2\" [color=\"#000000\", penwidth=3, tooltip=\"chr1\"];")
+        );
+        assert!(lines.contains(
+            &"\"4\" -> \"5\" [color=\"#000000:#1f77b4\", penwidth=3, tooltip=\"chr1, alt1\"];"
+        ));
+        assert!(
+            lines.contains(&"\"1\" -> \"3\" [color=\"#1f77b4\", penwidth=1, tooltip=\"alt1\"];")
+        );
+        assert_eq!(lines.last(), Some(&"}"));
+    }
+}
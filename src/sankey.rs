@@ -0,0 +1,217 @@
+use crate::graph::VgGraph;
+use crate::lib::Region;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
+
+// The sankey document of the non-raw `/graph`, formerly built by `proto/graph-helper.rb`.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Sankey {
+    pub links: Vec<SankeyLink>,
+    pub nodes: Vec<SankeyNode>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SankeyNode {
+    pub name: String,
+    pub length: f64,
+    pub sequence: usize,
+    pub path: BTreeMap<String, Vec<u64>>, // Path name to [rank, coordinate].
+    pub raw_seq: String,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SankeyLink {
+    pub source: Option<usize>,
+    pub target: Option<usize>,
+    pub value: u64,
+    pub path: String,
+    pub coord: Value, // A coordinate, or "" on edges not on any path.
+}
+
+// The coordinate of the node on the path, if the node index has it on the same path.
+fn coordinate(
+    regions: &HashMap<u64, Region>,
+    node_id: u64,
+    name: &str,
+    prefix: &str,
+) -> Option<u64> {
+    regions.get(&node_id).and_then(|region| {
+        if region.path == name || region.path == name.trim_start_matches(prefix) {
+            Some(region.start)
+        } else {
+            None
+        }
+    })
+}
+
+// Coordinates already on mappings are used first, then the ones of `regions`.
+pub fn sankey(graph: &VgGraph, chr_prefix: &str, regions: &HashMap<u64, Region>) -> Sankey {
+    let mut path_hash: HashMap<u64, BTreeMap<String, Vec<u64>>> = HashMap::new();
+    let mut edge_hash: HashMap<u64, Vec<usize>> = HashMap::new();
+    let mut edge_list: Vec<String> = vec![];
+
+    for path in graph.path.iter() {
+        let pathname = if path.name.starts_with(chr_prefix) {
+            path.name.clone()
+        } else {
+            format!("{}{}", chr_prefix, path.name)
+        };
+        for mapping in path.mapping.iter() {
+            let node_id = mapping.position.node_id;
+            let mut item = vec![mapping.rank.unwrap_or(0)];
+            if let Some(coord) = mapping
+                .position
+                .coordinate
+                .or_else(|| coordinate(regions, node_id, &path.name, chr_prefix))
+            {
+                item.push(coord);
+            }
+            path_hash
+                .entry(node_id)
+                .or_insert(BTreeMap::new())
+                .insert(pathname.clone(), item);
+        }
+        for pair in path.mapping.windows(2) {
+            let edge_id = edge_list.len();
+            edge_hash
+                .entry(pair[0].position.node_id)
+                .or_insert(vec![])
+                .push(edge_id);
+            edge_hash
+                .entry(pair[1].position.node_id)
+                .or_insert(vec![])
+                .push(edge_id);
+            edge_list.push(pathname.clone());
+        }
+    }
+
+    let mut node_hash: HashMap<u64, usize> = HashMap::new();
+    let mut nodes = vec![];
+    for (i, node) in graph.node.iter().enumerate() {
+        node_hash.insert(node.id, i);
+        nodes.push(SankeyNode {
+            name: node.id.to_string(),
+            length: ((node.length() + 1) as f64).log2() / 10.0,
+            sequence: node.length() as usize,
+            path: path_hash.get(&node.id).cloned().unwrap_or_default(),
+            raw_seq: node.sequence.clone(),
+        });
+    }
+
+    let empty = vec![];
+    let mut links = vec![];
+    for edge in graph.edge.iter() {
+        // It ignores repeat currently.
+        if edge.from == edge.to {
+            continue;
+        }
+        let to_edges = edge_hash.get(&edge.to).unwrap_or(&empty);
+        let mut shared: Vec<usize> = vec![];
+        for edge_id in edge_hash.get(&edge.from).unwrap_or(&empty).iter() {
+            if to_edges.contains(edge_id) && !shared.contains(edge_id) {
+                shared.push(*edge_id);
+            }
+        }
+        let source = node_hash.get(&edge.from).cloned();
+        let target = node_hash.get(&edge.to).cloned();
+        if shared.is_empty() {
+            links.push(SankeyLink {
+                source: source,
+                target: target,
+                value: 1,
+                path: "".to_string(),
+                coord: json!(""),
+            });
+        }
+        for edge_id in shared.into_iter() {
+            let path = &edge_list[edge_id];
+            let coord = path_hash
+                .get(&edge.from)
+                .and_then(|t| t.get(path))
+                .and_then(|t| t.get(1))
+                .map(|t| json!(t))
+                .unwrap_or(Value::Null);
+            links.push(SankeyLink {
+                source: source,
+                target: target,
+                value: 4, //FIXME()This is Magic number.
+                path: path.clone(),
+                coord: coord,
+            });
+        }
+    }
+
+    Sankey {
+        links: links,
+        nodes: nodes,
+    }
+}
+
+pub fn write_sankey(
+    file: &File,
+    graph: &VgGraph,
+    chr_prefix: &str,
+    regions: &HashMap<u64, Region>,
+) -> Result<bool, Error> {
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer(&mut writer, &sankey(graph, chr_prefix, regions))?;
+    writer.flush()?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{add_coordinate, MemoryGraph};
+    use crate::lib::OptionalRegion;
+    use serde_json;
+    use std::path::Path;
+
+    #[test]
+    fn sankey_works() {
+        let graph = MemoryGraph::from_vg_json(Path::new("test/graph/tiny.json")).unwrap();
+        let region = OptionalRegion::new("chr1:4-5".to_string()).unwrap();
+        let mut subgraph = graph.subgraph(&region, 1).unwrap();
+        add_coordinate(&mut subgraph, "chr");
+        let mut regions = HashMap::new();
+        regions.insert(
+            4,
+            Region {
+                path: "alt1".to_string(),
+                start: 5,
+                stop: 9,
+            },
+        );
+        let result = sankey(&subgraph, "chr", &regions);
+        let names: Vec<&str> = result.nodes.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["1", "2", "4"]);
+        assert_eq!(result.nodes[0].raw_seq, "ACGT");
+        assert_eq!(result.nodes[1].path.get("chr1"), Some(&vec![2, 4]));
+        assert_eq!(result.nodes[0].path.get("chralt1"), Some(&vec![1]));
+        assert_eq!(result.nodes[2].path.get("chralt1"), Some(&vec![3, 5]));
+
+        assert_eq!(result.links.len(), 2);
+        assert_eq!(result.links[0].source, Some(0));
+        assert_eq!(result.links[0].target, Some(1));
+        assert_eq!(result.links[0].path, "chr1");
+        assert_eq!(result.links[0].coord, json!(0));
+        assert_eq!(result.links[0].value, 4);
+    }
+
+    #[test]
+    fn sankey_off_path_edge_works() {
+        let graph: VgGraph = serde_json::from_str(
+            r#"{"node": [{"id": 1, "sequence": "A"}, {"id": 2, "sequence": "C"}],
+                "edge": [{"from": 1, "to": 2}, {"from": 2, "to": 2}]}"#,
+        )
+        .unwrap();
+        let result = sankey(&graph, "chr", &HashMap::new());
+        assert_eq!(result.links.len(), 1);
+        assert_eq!(result.links[0].value, 1);
+        assert_eq!(result.links[0].path, "");
+        assert_eq!(result.links[0].coord, json!(""));
+        assert_eq!(result.nodes[0].length, 0.1);
+    }
+}
@@ -0,0 +1,306 @@
+use crate::lib::{ConfigData, Region};
+use bio::alphabets::dna;
+use bio::io::fasta::IndexedReader;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Mutex;
+
+const TWOBIT_SIGNATURE: u32 = 0x1A41_2743;
+
+// A reader of UCSC .2bit files. Soft-masked bases are returned in lower case.
+pub struct TwoBit {
+    reader: BufReader<File>,
+    swapped: bool,
+    offsets: HashMap<String, u64>,
+}
+
+impl TwoBit {
+    pub fn open(path: &Path) -> io::Result<TwoBit> {
+        let mut twobit = TwoBit {
+            reader: BufReader::new(File::open(path)?),
+            swapped: false,
+            offsets: HashMap::new(),
+        };
+        match twobit.read_u32()? {
+            TWOBIT_SIGNATURE => {}
+            t if t.swap_bytes() == TWOBIT_SIGNATURE => twobit.swapped = true,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Not a 2bit file",
+                ))
+            }
+        }
+        // Version 1 files, for genomes over 4 GB, only widen the offsets to 64 bits.
+        let version = twobit.read_u32()?;
+        if version > 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsupported 2bit version {}", version),
+            ));
+        }
+        let count = twobit.read_u32()?;
+        let _reserved = twobit.read_u32()?;
+        for _ in 0..count {
+            let mut size = [0u8; 1];
+            twobit.reader.read_exact(&mut size)?;
+            let mut name = vec![0u8; size[0] as usize];
+            twobit.reader.read_exact(&mut name)?;
+            let offset = match version {
+                0 => twobit.read_u32()? as u64,
+                _ => twobit.read_u64()?,
+            };
+            twobit
+                .offsets
+                .insert(String::from_utf8_lossy(&name).into_owned(), offset);
+        }
+        Ok(twobit)
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0u8; 4];
+        self.reader.read_exact(&mut bytes)?;
+        let value = u32::from_le_bytes(bytes);
+        Ok(if self.swapped {
+            value.swap_bytes()
+        } else {
+            value
+        })
+    }
+
+    fn read_u64(&mut self) -> io::Result<u64> {
+        let mut bytes = [0u8; 8];
+        self.reader.read_exact(&mut bytes)?;
+        let value = u64::from_le_bytes(bytes);
+        Ok(if self.swapped {
+            value.swap_bytes()
+        } else {
+            value
+        })
+    }
+
+    // Pairs of (start, size).
+    fn read_blocks(&mut self) -> io::Result<Vec<(u64, u64)>> {
+        let count = self.read_u32()?;
+        let mut starts = vec![];
+        for _ in 0..count {
+            starts.push(self.read_u32()? as u64);
+        }
+        let mut blocks = vec![];
+        for start in starts.into_iter() {
+            blocks.push((start, self.read_u32()? as u64));
+        }
+        Ok(blocks)
+    }
+
+    pub fn names(&self) -> Vec<&String> {
+        self.offsets.keys().collect()
+    }
+
+    // Bases in [start, stop), clipped to the end of the sequence.
+    pub fn sequence(&mut self, name: &str, start: u64, stop: u64) -> io::Result<String> {
+        let offset = match self.offsets.get(name) {
+            Some(offset) => *offset,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} is not in the 2bit file", name),
+                ))
+            }
+        };
+        self.reader.seek(SeekFrom::Start(offset))?;
+        let size = self.read_u32()? as u64;
+        let n_blocks = self.read_blocks()?;
+        let mask_blocks = self.read_blocks()?;
+        let _reserved = self.read_u32()?;
+        let stop = stop.min(size);
+        if start >= stop {
+            return Ok(String::new());
+        }
+
+        let mut packed = vec![0u8; ((stop + 3) / 4 - start / 4) as usize];
+        self.reader.seek(SeekFrom::Current((start / 4) as i64))?;
+        self.reader.read_exact(&mut packed)?;
+        let mut bases: Vec<u8> = (start..stop)
+            .map(|i| {
+                let byte = packed[(i / 4 - start / 4) as usize];
+                b"TCAG"[((byte >> (6 - 2 * (i % 4))) & 3) as usize]
+            })
+            .collect();
+        for &(block_start, block_size) in n_blocks.iter() {
+            for i in block_start.max(start)..(block_start + block_size).min(stop) {
+                bases[(i - start) as usize] = b'N';
+            }
+        }
+        for &(block_start, block_size) in mask_blocks.iter() {
+            for i in block_start.max(start)..(block_start + block_size).min(stop) {
+                bases[(i - start) as usize].make_ascii_lowercase();
+            }
+        }
+        Ok(String::from_utf8_lossy(&bases).into_owned())
+    }
+}
+
+fn fasta_sequence(
+    fasta: &String,
+    name: &str,
+    start: u64,
+    stop: u64,
+) -> Result<String, Box<dyn Error>> {
+    let mut reader = IndexedReader::from_file(fasta)?;
+    let mut seq = vec![];
+    reader.fetch(name, start, stop)?;
+    reader.read(&mut seq)?;
+    Ok(String::from_utf8(seq)?)
+}
+
+// The `source.twobit` of each dataset, opened once at startup. Datasets whose file can't be
+// opened are logged and left out.
+pub fn open_twobits(data: &Vec<ConfigData>) -> BTreeMap<String, Mutex<TwoBit>> {
+    let mut twobits = BTreeMap::new();
+    for data in data.iter() {
+        if let Some(ref path) = data.source.twobit {
+            if path.is_empty() {
+                continue;
+            }
+            match TwoBit::open(Path::new(path)) {
+                Ok(twobit) => {
+                    twobits.insert(data.name.clone(), Mutex::new(twobit));
+                }
+                Err(e) => error!("could not open {}: {}", path, e),
+            }
+        }
+    }
+    twobits
+}
+
+// Reference bases of the region on the dataset, from `source.twobit` opened as `twobit`, or else
+// `source.reference`. The reverse complement is returned if `reverse` is set.
+pub fn reference_sequence(
+    data: &ConfigData,
+    twobit: Option<&Mutex<TwoBit>>,
+    region: &Region,
+    reverse: bool,
+) -> Result<String, Box<dyn Error>> {
+    let name = data.chr_prefix.clone() + &region.path;
+    let seq = match (twobit, &data.source.twobit, &data.source.reference) {
+        (Some(twobit), _, _) => {
+            twobit
+                .lock()
+                .unwrap()
+                .sequence(&name, region.start, region.stop)?
+        }
+        (None, &Some(ref path), _) if !path.is_empty() => {
+            return Err(From::from(format!("{} could not be opened", path)))
+        }
+        (_, _, &Some(ref fasta)) if !fasta.is_empty() => {
+            fasta_sequence(fasta, &name, region.start, region.stop)?
+        }
+        _ => return Err(From::from("The dataset has no reference sequence")),
+    };
+    match reverse {
+        true => Ok(String::from_utf8(dna::revcomp(seq.as_bytes()))?),
+        false => Ok(seq),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::ConfigSource;
+    use crate::utils::time;
+    use std::env;
+    use std::fs;
+
+    fn data(twobit: Option<&str>, reference: Option<&str>) -> ConfigData {
+        ConfigData {
+            name: "tiny".to_string(),
+            desc: None,
+            ref_id: "tiny".to_string(),
+            source: ConfigSource {
+                vg: None,
+                rocksdb: None,
+                xg: "".to_string(),
+                gam: None,
+                gamindex: None,
+                csv: None,
+                json: None,
+                gfa: None,
+                reference: reference.map(|t| t.to_string()),
+                twobit: twobit.map(|t| t.to_string()),
+                node_index: None,
+            },
+            chr_prefix: "chr".to_string(),
+            features: vec![],
+            static_files: vec![],
+            max_interval: None,
+        }
+    }
+
+    #[test]
+    fn twobit_works() {
+        let mut twobit = TwoBit::open(Path::new("test/graph/tiny.2bit")).unwrap();
+        assert_eq!(twobit.sequence("chr1", 0, 10).unwrap(), "ACGTTCCCCA");
+        assert_eq!(twobit.sequence("chr1", 3, 6).unwrap(), "TTC");
+        assert_eq!(twobit.sequence("chr1", 8, 100).unwrap(), "CA");
+        assert_eq!(twobit.sequence("chrM", 0, 6).unwrap(), "GAtcNN");
+        assert!(twobit.sequence("chr2", 0, 6).is_err());
+
+        // The same file with 64-bit offsets, and with a version that is not known.
+        let bytes = fs::read("test/graph/tiny.2bit").unwrap();
+        let count = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize;
+        let mut v1 = bytes[..16].to_vec();
+        v1[4] = 1;
+        let mut pos = 16;
+        for _ in 0..count {
+            let size = bytes[pos] as usize;
+            v1.extend_from_slice(&bytes[pos..pos + 1 + size]);
+            pos += 1 + size;
+            let offset =
+                u32::from_le_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]])
+                    as u64;
+            v1.extend_from_slice(&(offset + 4 * count as u64).to_le_bytes());
+            pos += 4;
+        }
+        v1.extend_from_slice(&bytes[pos..]);
+        let path = env::temp_dir().join(format!("ggbb-twobit-{}.2bit", time()));
+        fs::write(&path, &v1).unwrap();
+        let mut twobit = TwoBit::open(&path).unwrap();
+        assert_eq!(twobit.sequence("chrM", 0, 6).unwrap(), "GAtcNN");
+        v1[4] = 2;
+        fs::write(&path, &v1).unwrap();
+        let e = TwoBit::open(&path).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reference_sequence_works() {
+        let region = Region {
+            path: "M".to_string(),
+            start: 1,
+            stop: 5,
+        };
+        let twobits = open_twobits(&vec![data(Some("test/graph/tiny.2bit"), None)]);
+        let twobit = data(Some("test/graph/tiny.2bit"), None);
+        let fasta = data(None, Some("test/graph/tiny.fa"));
+        assert_eq!(
+            reference_sequence(&twobit, twobits.get("tiny"), &region, false).unwrap(),
+            "AtcN"
+        );
+        assert_eq!(
+            reference_sequence(&fasta, None, &region, false).unwrap(),
+            "AtcN"
+        );
+        assert_eq!(
+            reference_sequence(&fasta, None, &region, true).unwrap(),
+            "NgaT"
+        );
+        assert!(reference_sequence(&twobit, None, &region, false).is_err());
+        assert!(reference_sequence(&data(None, None), None, &region, false).is_err());
+    }
+}
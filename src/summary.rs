@@ -0,0 +1,335 @@
+use crate::graph::VgGraph;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+// The number of super-nodes a region is summarized into, unless a resolution is given.
+pub const DEFAULT_SUMMARY_NODES: u64 = 1000;
+
+// A summary of a region for zoomed-out views, where simple bubbles and linear chains
+// are collapsed into super-nodes of at most `resolution` bp.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Summary {
+    pub resolution: u64,
+    pub nodes: Vec<SuperNode>,
+    pub links: Vec<SuperLink>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SuperNode {
+    pub id: u64,       // The id of its first node, which links refer to.
+    pub nodes: u64,    // The number of nodes collapsed into it.
+    pub length: u64,   // Bases of its longest walk.
+    pub variants: u64, // The number of bubbles collapsed into it.
+    pub paths: BTreeSet<String>,
+    pub start: Option<u64>, // The span on the reference path, if it is on the path.
+    pub stop: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SuperLink {
+    pub source: u64,
+    pub target: u64,
+    pub paths: BTreeSet<String>,
+}
+
+impl SuperNode {
+    fn absorb(&mut self, other: SuperNode, length: u64) {
+        self.nodes += other.nodes;
+        self.length = length;
+        self.variants += other.variants;
+        self.paths.extend(other.paths);
+        self.start = min_option(self.start, other.start);
+        self.stop = self.stop.max(other.stop);
+    }
+}
+
+fn min_option(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        _ => a.or(b),
+    }
+}
+
+// Super-nodes with the links between them, collapsed until nothing more fits the resolution.
+struct Collapser {
+    nodes: BTreeMap<u64, SuperNode>,
+    succ: HashMap<u64, BTreeSet<u64>>,
+    pred: HashMap<u64, BTreeSet<u64>>,
+    parent: HashMap<u64, u64>, // Node id to the super-node it was merged into.
+    resolution: u64,
+}
+
+impl Collapser {
+    fn new(graph: &VgGraph, reference: &str, resolution: u64) -> Collapser {
+        let mut nodes: BTreeMap<u64, SuperNode> = graph
+            .node
+            .iter()
+            .map(|t| {
+                (
+                    t.id,
+                    SuperNode {
+                        id: t.id,
+                        nodes: 1,
+                        length: t.length(),
+                        variants: 0,
+                        paths: BTreeSet::new(),
+                        start: None,
+                        stop: None,
+                    },
+                )
+            })
+            .collect();
+        for path in graph.path.iter() {
+            for mapping in path.mapping.iter() {
+                if let Some(node) = nodes.get_mut(&mapping.position.node_id) {
+                    node.paths.insert(path.name.clone());
+                    if path.name != reference {
+                        continue;
+                    }
+                    if let Some(coord) = mapping.position.coordinate {
+                        node.start = min_option(node.start, Some(coord));
+                        node.stop = node.stop.max(Some(coord + node.length));
+                    }
+                }
+            }
+        }
+        let mut collapser = Collapser {
+            nodes: nodes,
+            parent: HashMap::new(),
+            succ: HashMap::new(),
+            pred: HashMap::new(),
+            resolution: resolution,
+        };
+        for edge in graph.edge.iter() {
+            // An edge from the start to the end is the same edge read backwards.
+            let (from, to) = match edge.from_start && edge.to_end {
+                true => (edge.to, edge.from),
+                false => (edge.from, edge.to),
+            };
+            if from != to
+                && collapser.nodes.contains_key(&from)
+                && collapser.nodes.contains_key(&to)
+            {
+                collapser.succ.entry(from).or_default().insert(to);
+                collapser.pred.entry(to).or_default().insert(from);
+            }
+        }
+        collapser
+    }
+
+    fn succ(&self, id: u64) -> Vec<u64> {
+        self.succ
+            .get(&id)
+            .map(|t| t.iter().cloned().collect())
+            .unwrap_or(vec![])
+    }
+
+    fn pred(&self, id: u64) -> Vec<u64> {
+        self.pred
+            .get(&id)
+            .map(|t| t.iter().cloned().collect())
+            .unwrap_or(vec![])
+    }
+
+    // Move `from` into `into`, whose links then take the place of the links of `from`.
+    fn merge(&mut self, into: u64, from: u64, length: u64) {
+        let node = match self.nodes.remove(&from) {
+            Some(node) => node,
+            None => return,
+        };
+        if let Some(target) = self.nodes.get_mut(&into) {
+            target.absorb(node, length);
+        }
+        for next in self.succ.remove(&from).unwrap_or_default() {
+            let preds = self.pred.entry(next).or_default();
+            preds.remove(&from);
+            if next != into {
+                preds.insert(into);
+                self.succ.entry(into).or_default().insert(next);
+            }
+        }
+        for prev in self.pred.remove(&from).unwrap_or_default() {
+            let succs = self.succ.entry(prev).or_default();
+            succs.remove(&from);
+            if prev != into {
+                succs.insert(into);
+                self.pred.entry(into).or_default().insert(prev);
+            }
+        }
+        if let Some(t) = self.succ.get_mut(&into) {
+            t.remove(&into);
+        }
+        if let Some(t) = self.pred.get_mut(&into) {
+            t.remove(&into);
+        }
+        self.parent.insert(from, into);
+    }
+
+    // The id of the super-node containing the node.
+    fn owner(&self, id: u64) -> Option<u64> {
+        let mut id = id;
+        while let Some(&parent) = self.parent.get(&id) {
+            id = parent;
+        }
+        match self.nodes.contains_key(&id) {
+            true => Some(id),
+            false => None,
+        }
+    }
+
+    // `id -> v` where `v` has no other predecessor.
+    fn collapse_chain(&mut self, id: u64) -> bool {
+        let succ = self.succ(id);
+        if succ.len() != 1 || self.pred(succ[0]) != vec![id] {
+            return false;
+        }
+        let length = self.nodes[&id].length + self.nodes[&succ[0]].length;
+        if length > self.resolution {
+            return false;
+        }
+        self.merge(id, succ[0], length);
+        true
+    }
+
+    // `id -> {b1, .., bk} -> t`, where each branch only connects `id` and `t`.
+    // A direct edge `id -> t`, e.g. a deletion, is one more branch.
+    fn collapse_bubble(&mut self, id: u64) -> bool {
+        let succ = self.succ(id);
+        if succ.len() < 2 {
+            return false;
+        }
+        let mut sink = None;
+        let mut branches = vec![];
+        for &b in succ.iter() {
+            let next = self.succ(b);
+            if self.pred(b) == vec![id] && next.len() == 1 {
+                if sink.is_some() && sink != Some(next[0]) {
+                    return false;
+                }
+                sink = Some(next[0]);
+                branches.push(b);
+            }
+        }
+        let sink = match sink {
+            Some(sink) if sink != id => sink,
+            _ => return false,
+        };
+        // Every successor is either a branch or the sink itself.
+        if branches.len() + succ.contains(&sink) as usize != succ.len() {
+            return false;
+        }
+        let longest = branches
+            .iter()
+            .map(|t| self.nodes[t].length)
+            .max()
+            .unwrap_or(0);
+        let length = self.nodes[&id].length + longest;
+        if length > self.resolution {
+            return false;
+        }
+        for b in branches.into_iter() {
+            self.merge(id, b, length);
+        }
+        if let Some(node) = self.nodes.get_mut(&id) {
+            node.variants += 1;
+        }
+        true
+    }
+
+    fn collapse(&mut self) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            let ids: Vec<u64> = self.nodes.keys().cloned().collect();
+            for id in ids.into_iter() {
+                if !self.nodes.contains_key(&id) {
+                    continue;
+                }
+                while self.collapse_bubble(id) || self.collapse_chain(id) {
+                    changed = true;
+                }
+            }
+        }
+    }
+}
+
+// Collapse the graph of a region into super-nodes of at most `resolution` bp;
+// coordinates of super-nodes are the ones on the `reference` path.
+pub fn summarize(graph: &VgGraph, reference: &str, resolution: u64) -> Summary {
+    let mut collapser = Collapser::new(graph, reference, resolution);
+    collapser.collapse();
+
+    let mut links: BTreeMap<(u64, u64), BTreeSet<String>> = BTreeMap::new();
+    for (&from, succ) in collapser.succ.iter() {
+        for &to in succ.iter() {
+            links.insert((from, to), BTreeSet::new());
+        }
+    }
+    for path in graph.path.iter() {
+        let owners: Vec<u64> = path
+            .mapping
+            .iter()
+            .filter_map(|t| collapser.owner(t.position.node_id))
+            .collect();
+        for pair in owners.windows(2) {
+            if let Some(paths) = links.get_mut(&(pair[0], pair[1])) {
+                paths.insert(path.name.clone());
+            }
+        }
+    }
+    Summary {
+        resolution: resolution,
+        nodes: collapser.nodes.into_iter().map(|(_, t)| t).collect(),
+        links: links
+            .into_iter()
+            .map(|((source, target), paths)| SuperLink {
+                source: source,
+                target: target,
+                paths: paths,
+            })
+            .collect(),
+    }
+}
+
+// The resolution giving about `DEFAULT_SUMMARY_NODES` super-nodes over the interval.
+pub fn default_resolution(interval: u64) -> u64 {
+    interval / DEFAULT_SUMMARY_NODES
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::tiny_subgraph;
+
+    #[test]
+    fn summarize_works() {
+        let graph = tiny_subgraph();
+        let full = summarize(&graph, "chr1", 0);
+        assert_eq!(full.nodes.len(), 5);
+        assert_eq!(full.links.len(), 5);
+        assert_eq!(full.nodes[3].start, Some(5));
+        assert_eq!(full.nodes[3].stop, Some(9));
+
+        // The SNP bubble of 2 and 3 fits into node 1, then 4 and 5 are a chain.
+        let summary = summarize(&graph, "chr1", 5);
+        let ids: Vec<u64> = summary.nodes.iter().map(|t| t.id).collect();
+        assert_eq!(ids, vec![1, 4]);
+        assert_eq!(summary.nodes[0].length, 5);
+        assert_eq!(summary.nodes[0].nodes, 3);
+        assert_eq!(summary.nodes[0].variants, 1);
+        assert_eq!(
+            (summary.nodes[0].start, summary.nodes[0].stop),
+            (Some(0), Some(5))
+        );
+        assert_eq!(summary.nodes[1].length, 5);
+        assert_eq!(summary.links.len(), 1);
+        assert_eq!((summary.links[0].source, summary.links[0].target), (1, 4));
+        let paths: Vec<&str> = summary.links[0].paths.iter().map(|t| t.as_str()).collect();
+        assert_eq!(paths, vec!["alt1", "chr1"]);
+
+        let whole = summarize(&graph, "chr1", 100);
+        assert_eq!(whole.nodes.len(), 1);
+        assert_eq!(whole.nodes[0].length, 10);
+        assert_eq!(whole.nodes[0].nodes, 5);
+        assert!(whole.links.is_empty());
+    }
+}
@@ -38,26 +38,3 @@ pub fn url_compose(url: &iron::Url, path: &str) -> Result<Url, String> {
     // println!("{}", new_url);
     Url::parse(&new_url)
 }
-
-/*
-pub fn remove_tmp(args: Args) {
-    let demo_dir = fs::read_dir(args.flag_tmp);
-    delete_dir_contents(demo_dir);
-}
-
-fn delete_dir_contents(read_dir_res: Result<ReadDir, io::Error>) {
-    if let Ok(dir) = read_dir_res {
-        for entry in dir {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-
-                if path.is_dir() {
-                    fs::remove_dir_all(path).expect("Failed to remove a dir");
-                } else {
-                    fs::remove_file(path).expect("Failed to remove a file");
-                }
-            };
-        }
-    };
-}
-*/
@@ -1,10 +1,24 @@
+use crate::gfa::Gfa;
+use crate::graph::Native;
 use iron::Url;
-use crate::lib::{Config, OptionalRegion};
+use crate::annotations::node_ids_to_regions;
+use crate::error::region_too_large;
+use crate::fasta::write_fasta;
+use crate::gfa::write_gfa;
+use crate::graph::{
+    add_coordinate, downsample_graph, node_graph_file, set_coordinates, write_subgraph,
+    GraphFormat, VgGraph,
+};
+use crate::lib::{Config, ConfigData, Database, OptionalRegion, Region};
+use crate::process::{group_command, Pipeline};
+use crate::sankey::write_sankey;
+use crate::summary::{summarize, Summary};
 use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
 use std::fs::metadata;
 use std::fs::File;
 use std::io::Error;
-use std::io::Write;
 use std::path::Path;
 use std::process::Command;
 use std::process::Stdio;
@@ -41,9 +55,12 @@ pub trait Graph {
         _: &File,
         _: &Option<i64>,
         _: &Config,
+        _: &ConfigData,
+        _: &Database,
         _: &String,
         _: bool,
-        _: &String,
+        _: u64,
+        _: bool,
     ) -> Result<bool, Error>;
     fn generate_graph_to_file_wo_helper(
         &self,
@@ -52,13 +69,36 @@ pub trait Graph {
         _: &Path,
         _: &Option<i64>,
         _: &Config,
+        _: &ConfigData,
+        _: &Database,
         _: &String,
         _: bool,
-        _: &String,
+        _: u64,
+        _: bool,
         _: bool,
         _: i32,
+        _: i32,
     ) -> Result<bool, Error>;
+    // Summarize the region into super-nodes of at most the resolution in bp.
+    fn generate_summary(
+        &self,
+        _: &OptionalRegion,
+        _: &Option<i64>,
+        _: &Config,
+        _: &ConfigData,
+        _: &Database,
+        _: u64,
+        _: u64,
+    ) -> Result<Summary, Error>;
     fn version(&self, config: &Config) -> i32;
+    fn path_names(&self, _: &Config, _: &ConfigData) -> Result<Vec<String>, Error>;
+    fn node_sequence(
+        &self,
+        _: &Config,
+        _: &ConfigData,
+        _: &Database,
+        _: u64,
+    ) -> Result<Option<String>, Error>;
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -67,6 +107,18 @@ pub struct VG {}
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum GraphDB {
     VG(VG),
+    Native(Native),
+    Gfa(Gfa),
+}
+
+impl GraphDB {
+    pub fn as_graph(&self) -> &dyn Graph {
+        match *self {
+            GraphDB::VG(ref vg) => vg,
+            GraphDB::Native(ref native) => native,
+            GraphDB::Gfa(ref gfa) => gfa,
+        }
+    }
 }
 /*
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -82,8 +134,12 @@ impl GraphConfig {
 }
 */
 
+// Converts an uploaded vcf into an xg; it is not part of this repository and is installed separately.
+pub const VCF2XG_SCRIPT: &'static str = "script/vcf2xg.sh";
+
 impl VG {
-    pub fn spawn_vcf_for_visualize(
+    // The command converting an uploaded vcf into an xg, run as a job.
+    pub fn vcf_for_visualize_command(
         &self,
         vcf_name: &String,
         uuid: &String,
@@ -92,46 +148,59 @@ impl VG {
         is_pcf: bool,
         reference: Option<&String>,
         filename: Option<&String>,
-    ) -> Result<(), Error> {
-        // Spawn and do not wait until end runs.
-        // Currently, DUMMY script will running.
-        let name = vcf_name.clone();
+    ) -> Vec<String> {
         let vcf_or_pcf = if is_pcf { "pcf" } else { "vcf" };
-        let mut _command1 = Command::new("bash")
-            .args(&[
-                "script/vcf2xg.sh",
-                &name,
-                &uuid,
-                &config.bin.vg_tmp,
-                &current_dir,
-                vcf_or_pcf,
-                reference.unwrap_or(&("hg19".to_string())),
-                filename.unwrap_or(&("".to_string())),
-            ])
-            .spawn()?;
-        return Ok(());
+        let hg19 = "hg19".to_string();
+        let empty = "".to_string();
+        vec![
+            "bash",
+            VCF2XG_SCRIPT,
+            vcf_name,
+            uuid,
+            &config.bin.vg_tmp,
+            current_dir,
+            vcf_or_pcf,
+            reference.unwrap_or(&hg19),
+            filename.unwrap_or(&empty),
+        ]
+        .iter()
+        .map(|t| t.to_string())
+        .collect()
     }
     fn replace_file_name(region: &OptionalRegion, path: &String) -> String {
         path.replace("{}", &region.path)
     }
-    fn download_file(&self, url: &String, filename: &String, _args: &Args) -> Result<(), Error> {
-        let mut command3 = Command::new("curl")
-            .args(&[url, "-S", "-L", "-k", "-o", &filename, "-m", "100"])
-            .spawn()?;
-
-        let _status = command3.wait()?;
+    fn download_file(&self, url: &String, filename: &String, config: &Config) -> Result<(), Error> {
+        let timeout = config.bin.download_timeout();
+        let mut pipeline = Pipeline::new(timeout + 1);
+        pipeline.spawn(
+            "curl",
+            Command::new("curl").args(&[
+                url,
+                "-S",
+                "-L",
+                "-k",
+                "-o",
+                &filename,
+                "-m",
+                &timeout.to_string(),
+            ]),
+        )?;
+        pipeline.wait()?;
         return Ok(());
     }
     pub fn generate_graph_to_file_custom(
         &self,
         path: OptionalRegion,
-        data: i64,
+        data_id: i64,
         file: &File,
         steps: &Option<i64>,
         config: &Config,
+        data: &ConfigData,
         json: &Option<String>,
         xgpath_old: &Option<String>,
         args: &Args,
+        format: GraphFormat,
     ) -> Result<bool, Error> {
         let xgpath_clone = xgpath_old.clone();
         let mut xgpath = xgpath_clone.unwrap_or("".to_string());
@@ -144,7 +213,7 @@ impl VG {
                 .to_string(); // unwrap();
             xgpath = format!("{}/xg/{}", args.flag_tmp, xgpath);
             if let Err(_) = metadata(Path::new(&xgpath)) {
-                if let Err(_) = self.download_file(&(format!("{}", url)), &xgpath, args) {
+                if let Err(_) = self.download_file(&(format!("{}", url)), &xgpath, config) {
                     debug!("Error download: {:?}", url);
                 }
             }
@@ -156,16 +225,19 @@ impl VG {
         }
         let json_clone = json.clone();
         match &json_clone.unwrap_or("".to_string()).as_ref() {
-            &"" => self
-                .generate_graph_to_file_from_vg_to_json(path, data, file, steps, config, &xgpath),
+            &"" => self.generate_graph_to_file_from_vg_to_json(
+                path, data_id, file, steps, config, &xgpath, format,
+            ),
             k => self.generate_graph_to_file_from_json(
                 path,
-                data,
+                data_id,
                 file,
                 steps,
                 config,
+                data,
                 &k.to_string(),
                 &xgpath,
+                format,
             ),
         }
     }
@@ -178,34 +250,50 @@ impl VG {
         _steps: &Option<i64>,
         config: &Config,
         xgfile: &String,
+        format: GraphFormat,
     ) -> Result<bool, Error> {
-        let out = unsafe { Stdio::from_raw_fd(file.as_raw_fd()) };
-        let xgpath = VG::replace_file_name(&path, xgfile); //&config.data[0].source.xg);
+        let xgpath = VG::replace_file_name(&path, xgfile); //&data.source.xg);
         let path = format!("{}", path);
         debug!("{}, {}", xgpath, path);
         let commands: Vec<&str> = config.bin.vg.split(" ").collect();
-        let command1 = Command::new("cat")
-            .args(&[xgpath])
-            .stdout(Stdio::piped())
-            .spawn()?;
-        //.expect("failed to spawn a process");
-        let mut command2 = Command::new(&commands[0])
-            .args(&commands[1..])
-            .args(&["view", "-j", "-"])
-            .stdin(unsafe { Stdio::from_raw_fd(command1.stdout.as_ref().unwrap().as_raw_fd()) })
-            .stdout(out)
-            .spawn()?;
-        //.expect("failed to spawn a process");
-        //.output().unwrap_or_else(|e| {
-        //    panic!("failed to execute process: {}", e)
-        //});
-        let status2 = command2.wait()?; //.expect("failed to wait on child");
-
-        if status2.success() {
+        let mut pipeline =
+            Pipeline::with_slots(config.bin.find_timeout(), 1, config.bin.max_processes())?;
+        pipeline.spawn(
+            "cat",
+            Command::new("cat").args(&[xgpath]).stdout(Stdio::piped()),
+        )?;
+        let stdout1 = pipeline.take_stdout()?;
+        if format == GraphFormat::Gfa || format == GraphFormat::Fasta {
+            pipeline.spawn(
+                "vg view",
+                Command::new(&commands[0])
+                    .args(&commands[1..])
+                    .args(&["view", "-j", "-"])
+                    .stdin(Stdio::from(stdout1))
+                    .stdout(Stdio::piped()),
+            )?;
+            pipeline.capture_stdout();
+            let mut graph = VgGraph::from_reader(&pipeline.wait()?[..])?;
+            match format {
+                GraphFormat::Gfa => write_gfa(file, &graph)?,
+                _ => {
+                    graph.fill_coordinates();
+                    write_fasta(file, &graph)?
+                }
+            }
             return Ok(true);
-        } else {
-            return Ok(false);
         }
+        let out = unsafe { Stdio::from_raw_fd(file.as_raw_fd()) };
+        pipeline.spawn(
+            "vg view",
+            Command::new(&commands[0])
+                .args(&commands[1..])
+                .args(&["view", "-j", "-"])
+                .stdin(Stdio::from(stdout1))
+                .stdout(out),
+        )?;
+        pipeline.wait()?;
+        return Ok(true);
     }
 
     pub fn generate_graph_to_file_from_json(
@@ -214,42 +302,128 @@ impl VG {
         _data: i64,
         file: &File,
         _steps: &Option<i64>,
-        config: &Config,
+        _config: &Config,
+        data: &ConfigData,
         json: &String,
         xgpath: &String,
+        format: GraphFormat,
     ) -> Result<bool, Error> {
-        let out = unsafe { Stdio::from_raw_fd(file.as_raw_fd()) };
         debug!("Saved: {}", xgpath);
-        let chr_prefix = &config.data[0].chr_prefix;
-        let mut command2 = Command::new("tee")
-            .args(&[xgpath])
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn()?;
-        let mut command3 = Command::new("ruby")
-            .args(&[
-                "proto/graph-helper.rb",
-                &config.bin.vg,
-                xgpath.as_ref(),
-                chr_prefix.as_ref(),
-            ])
-            .stdin(unsafe { Stdio::from_raw_fd(command2.stdout.as_ref().unwrap().as_raw_fd()) })
-            .stdout(out)
-            .spawn()?;
+        fs::write(xgpath, json)?;
+        let mut graph = VgGraph::from_reader(json.as_bytes())?;
+        if format == GraphFormat::Gfa {
+            return write_gfa(file, &graph).map(|_| true);
+        }
+        graph.fill_coordinates();
+        match format {
+            GraphFormat::Fasta => write_fasta(file, &graph).map(|_| true),
+            _ => write_sankey(file, &graph, &data.chr_prefix, &HashMap::new()),
+        }
+    }
+
+    // `vg find | vg view -j`, parsed in process.
+    fn find_subgraph(
+        &self,
+        config: &Config,
+        commands: &Vec<&str>,
+        xgpath: &String,
+        query: &[&str],
+    ) -> Result<VgGraph, Error> {
+        let mut pipeline =
+            Pipeline::with_slots(config.bin.find_timeout(), 2, config.bin.max_processes())?;
+        pipeline.spawn(
+            "vg find",
+            Command::new(&commands[0])
+                .args(&commands[1..])
+                .args(&["find", "-x", xgpath.as_ref()])
+                .args(query)
+                .stdout(Stdio::piped()),
+        )?;
+        let stdout1 = pipeline.take_stdout()?;
+        pipeline.spawn(
+            "vg view",
+            Command::new(&commands[0])
+                .args(&commands[1..])
+                .args(&["view", "-j", "-"])
+                .stdin(Stdio::from(stdout1))
+                .stdout(Stdio::piped()),
+        )?;
+        pipeline.capture_stdout();
+        let output = pipeline.wait()?;
+        VgGraph::from_reader(&output[..])
+    }
 
-        let write = command2.stdin.as_mut().unwrap().write_all(json.as_bytes());
-        let status2 = command2.wait()?; //.expect("failed to wait on child");
-        let status3 = command3.wait()?; //.expect("failed to wait on child");
-        return Ok(status2.success() && status3.success() && write.is_ok());
+    // Set coordinates of mappings from positions of their nodes by `vg find -N -P`, which takes
+    // one path at a time, so that there is one process for each path of the graph.
+    fn set_path_coordinates(
+        &self,
+        config: &Config,
+        commands: &Vec<&str>,
+        xgpath: &String,
+        graph: &mut VgGraph,
+    ) -> Result<(), Error> {
+        let mut names: Vec<String> = vec![];
+        for path in graph.path.iter() {
+            if !names.contains(&path.name) {
+                names.push(path.name.clone());
+            }
+        }
+        for name in names.iter() {
+            let mut node_ids: Vec<String> = vec![];
+            for path in graph.path.iter().filter(|t| t.name == *name) {
+                for mapping in path.mapping.iter() {
+                    node_ids.push(mapping.position.node_id.to_string() + "\n");
+                }
+            }
+            let mut pipeline =
+                Pipeline::with_slots(config.bin.find_timeout(), 1, config.bin.max_processes())?;
+            pipeline.spawn(
+                "vg find",
+                Command::new(&commands[0])
+                    .args(&commands[1..])
+                    .args(&["find", "-N", "/dev/stdin", "-P", name, "-x", xgpath])
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped()),
+            )?;
+            pipeline.write_stdin(node_ids.concat().into_bytes());
+            pipeline.capture_stdout();
+            let output = pipeline.wait()?;
+            // The first position of each node on the path.
+            let mut coords: HashMap<u64, u64> = HashMap::new();
+            for line in String::from_utf8_lossy(&output).lines() {
+                let items: Vec<&str> = line.split("\t").collect();
+                if items.len() < 2 {
+                    continue;
+                }
+                if let (Ok(id), Ok(coord)) = (items[0].parse::<u64>(), items[1].parse::<u64>()) {
+                    coords.entry(id).or_insert(coord);
+                }
+            }
+            for path in graph.path.iter_mut().filter(|t| t.name == *name) {
+                for mapping in path.mapping.iter_mut() {
+                    if mapping.position.coordinate.is_none() {
+                        mapping.position.coordinate =
+                            coords.get(&mapping.position.node_id).cloned();
+                    }
+                }
+            }
+        }
+        Ok(())
     }
 
     pub fn test(&self, config: &Config) -> bool {
         let commands: Vec<&str> = config.bin.vg.split(" ").collect();
-        let output = Command::new(commands[0])
+        let output = match Command::new(commands[0])
             .args(&commands[1..])
             .arg("version")
             .output()
-            .expect("VG is not exist");
+        {
+            Ok(output) => output,
+            Err(e) => {
+                println!("VG is not exist: {}", e);
+                return false;
+            }
+        };
         if output.status.success() {
             info!("VG Version: {}", String::from_utf8_lossy(&output.stdout));
             info!("VG minor version: {}", self.version(config));
@@ -259,26 +433,56 @@ impl VG {
 
     pub fn version(&self, config: &Config) -> i32 {
         let commands: Vec<&str> = config.bin.vg.split(" ").collect();
-        let output = Command::new(commands[0])
+        let output = match Command::new(commands[0])
             .args(&commands[1..])
             .arg("version")
             .output()
-            .expect("VG is not exist");
+        {
+            Ok(output) => output,
+            Err(_) => return -1,
+        };
         if output.status.success() {
             let version = String::from_utf8_lossy(&output.stdout);
             let version_re = Regex::new(r"v1\.(\d+)\.").unwrap();
-            let caps = version_re.captures(&version).unwrap();
-            let cap = caps.get(1).unwrap();
-            let i: i32 = cap.as_str().parse().unwrap_or(-1);
-            return i;
+            // Development builds may not follow v1.x.y.
+            if let Some(cap) = version_re.captures(&version).and_then(|t| t.get(1)) {
+                return cap.as_str().parse().unwrap_or(-1);
+            }
         }
         let i = -1_i32;
         return i;
     }
 }
 
-const MAX_INTERVAL: u64 = 50000;
-const MAX_STEP: i64 = 10;
+pub const MAX_INTERVAL: u64 = 50000;
+pub const MAX_STEP: i64 = 10;
+pub const DEFAULT_STEP: i64 = 2;
+// A downsampled region may be this many times longer than the max interval.
+pub const MAX_DOWNSAMPLE_SCALE: u64 = 100;
+// The number of nodes a downsampled region is merged into.
+pub const DOWNSAMPLE_NODES: u64 = 1000;
+// The bases a merged node of a downsampled region keeps from the start of its members.
+pub const DOWNSAMPLE_SEQUENCE: usize = 8;
+
+// Whether the region has to be downsampled, or an error if it is too large even for that.
+pub fn check_interval(
+    path: &OptionalRegion,
+    max_interval: u64,
+    downsample: bool,
+) -> Result<bool, Error> {
+    let interval = path.interval().unwrap_or(0);
+    if interval <= max_interval {
+        return Ok(false);
+    }
+    if !downsample {
+        return Err(region_too_large(interval, max_interval));
+    }
+    let max_downsample = max_interval.saturating_mul(MAX_DOWNSAMPLE_SCALE);
+    if interval > max_downsample {
+        return Err(region_too_large(interval, max_downsample));
+    }
+    Ok(true)
+}
 
 impl Graph for VG {
     fn version(&self, config: &Config) -> i32 {
@@ -294,11 +498,15 @@ impl Graph for VG {
         file: &Path,
         steps: &Option<i64>,
         config: &Config,
+        data: &ConfigData,
+        database: &Database,
         xgfile: &String,
         tmp: bool,
-        max_interval: &String,
+        max_interval: u64,
+        downsample: bool,
         gam: bool,
         version: i32,
+        threads: i32,
     ) -> Result<bool, Error> {
         let chunk_prefix = format!(
             "{}{}",
@@ -307,26 +515,16 @@ impl Graph for VG {
                 .vg_volume_prefix
                 .clone()
                 .unwrap_or("".to_string()),
-            file.to_str().unwrap()
+            file.to_string_lossy()
         );
-        debug!("Create a temporary file: {:?}", file);
-        let file = File::create(file)?;
-        let out = unsafe { Stdio::from_raw_fd(file.as_raw_fd()) };
-        let mut steps = steps.unwrap_or(2);
+        let mut steps = steps.unwrap_or(DEFAULT_STEP);
         if steps > MAX_STEP {
             steps = MAX_STEP;
         }
-        let xgpath = VG::replace_file_name(&path, xgfile); //&config.data[0].source.xg);
-        if path.interval() > Some(max_interval.parse::<u64>().unwrap_or(MAX_INTERVAL)) {
-            debug!(
-                "path interval {:?} > the max interval {:?}",
-                path.interval(),
-                Some(max_interval.parse::<u64>().unwrap_or(MAX_INTERVAL))
-            );
-            return Ok(false);
-        }
+        let xgpath = VG::replace_file_name(&path, xgfile); //&data.source.xg);
+        let downsampled = check_interval(&path, max_interval, downsample)?;
         let path_str = format!("{}", path);
-        let chr_prefix = &config.data[0].chr_prefix;
+        let chr_prefix = &data.chr_prefix;
         info!("{}, {}", xgpath, path_str);
         // let result = panic::catch_unwind(|| {
         let command: String = match tmp {
@@ -335,15 +533,37 @@ impl Graph for VG {
         };
         let commands: Vec<&str> = command.split(" ").collect();
         info!("VG version: {}, cmd: {:?}", version, commands);
-        if let Some(ref gam_index_source) = config.data[0].source.gamindex {
+        if downsampled {
+            // Only the nodes on the path are extracted, and alignments are not shown.
+            let query = ["-p", path_str.as_ref(), "-c", "0"];
+            let mut graph = self.find_subgraph(config, &commands, &xgpath, &query)?;
+            // Uploaded graphs are not in the node index.
+            match tmp {
+                false => {
+                    let node_ids: Vec<u64> = graph.node.iter().map(|t| t.id).collect();
+                    let regions = node_ids_to_regions(data, database, &node_ids);
+                    set_coordinates(&mut graph, &regions, chr_prefix);
+                }
+                true => self.set_path_coordinates(config, &commands, &xgpath, &mut graph)?,
+            }
+            let mut graph = downsample_graph(&graph, &path.path, DOWNSAMPLE_NODES);
+            add_coordinate(&mut graph, chr_prefix);
+            write_subgraph(file, &graph)?;
+            return Ok(true);
+        }
+        debug!("Create a temporary file: {:?}", file);
+        let file = File::create(file)?;
+        let out = unsafe { Stdio::from_raw_fd(file.as_raw_fd()) };
+        if let Some(ref gam_index_source) = data.source.gamindex {
             if gam {
                 let steps_str = format!("{}", steps);
+                let threads_str = format!("{}", threads.max(1));
                 let chunk_command = if version >= 10 {
-                    if let Some(ref gam_source) = config.data[0].source.gam {
+                    if let Some(ref gam_source) = data.source.gam {
                         [
                             "chunk",
                             "-t",
-                            "4",
+                            threads_str.as_ref(),
                             "-x",
                             xgpath.as_ref(),
                             "-p",
@@ -361,7 +581,7 @@ impl Graph for VG {
                         [
                             "chunk",
                             "-t",
-                            "4",
+                            threads_str.as_ref(),
                             "-x",
                             xgpath.as_ref(),
                             "-p",
@@ -380,7 +600,7 @@ impl Graph for VG {
                     [
                         "chunk",
                         "-t",
-                        "4",
+                        threads_str.as_ref(),
                         "-x",
                         xgpath.as_ref(),
                         "-p",
@@ -398,7 +618,7 @@ impl Graph for VG {
                     [
                         "chunk",
                         "-t",
-                        "4",
+                        threads_str.as_ref(),
                         "-x",
                         xgpath.as_ref(),
                         "-p",
@@ -414,92 +634,155 @@ impl Graph for VG {
                     ]
                 };
                 debug!("{:?}", chunk_command);
-                let mut command1 = Command::new(&commands[0])
-                    .args(&commands[1..])
-                    .args(&chunk_command)
-                    .stdout(Stdio::piped())
-                    .spawn()?;
-                //.expect("failed to spawn a process");
-                let mut command2 = Command::new(&commands[0])
-                    .args(&commands[1..])
-                    .args(&["view", "-j", "-"])
-                    .stdin(unsafe {
-                        Stdio::from_raw_fd(command1.stdout.as_ref().unwrap().as_raw_fd())
-                    })
-                    .stdout(Stdio::piped())
-                    .spawn()?;
-                //.expect("failed to spawn a process");
-                let mut command3 = Command::new("ruby")
-                    .args(&[
-                        "proto/graph-helper2.rb",
-                        &command,
-                        xgpath.as_ref(),
-                        &path.path,
-                        chr_prefix.as_ref(),
-                        chunk_prefix.as_ref(),
-                    ])
-                    .stdin(unsafe {
-                        Stdio::from_raw_fd(command2.stdout.as_ref().unwrap().as_raw_fd())
-                    })
-                    .stdout(out)
-                    .spawn()?;
-                //.expect("failed to spawn a process");
-                let status1 = command1.wait()?; //.expect("failed to wait on child");
-                let status2 = command2.wait()?; //.expect("failed to wait on child");
-                let status3 = command3.wait()?; //.expect("failed to wait on child");
-                debug!("Status codes: {} {} {}", status1, status2, status3);
-                if status1.success() && status2.success() && status3.success() {
-                    return Ok(true);
-                } else {
-                    return Ok(false);
-                }
+                // The Ruby helper runs one more vg process at a time.
+                let mut pipeline = Pipeline::with_slots(
+                    config.bin.chunk_timeout(),
+                    3,
+                    config.bin.max_processes(),
+                )?;
+                pipeline.spawn(
+                    "vg chunk",
+                    Command::new(&commands[0])
+                        .args(&commands[1..])
+                        .args(&chunk_command)
+                        .stdout(Stdio::piped()),
+                )?;
+                let stdout1 = pipeline.take_stdout()?;
+                pipeline.spawn(
+                    "vg view",
+                    Command::new(&commands[0])
+                        .args(&commands[1..])
+                        .args(&["view", "-j", "-"])
+                        .stdin(Stdio::from(stdout1))
+                        .stdout(Stdio::piped()),
+                )?;
+                let stdout2 = pipeline.take_stdout()?;
+                pipeline.spawn_group(
+                    "graph-helper2.rb",
+                    group_command("ruby")
+                        .args(&[
+                            "proto/graph-helper2.rb",
+                            &command,
+                            xgpath.as_ref(),
+                            &path.path,
+                            chr_prefix.as_ref(),
+                            chunk_prefix.as_ref(),
+                        ])
+                        .stdin(Stdio::from(stdout2))
+                        .stdout(out),
+                )?;
+                pipeline.wait()?;
+                return Ok(true);
             }
         }
-        let mut command1 = Command::new(&commands[0])
-            .args(&commands[1..])
-            .args(&[
-                "find",
-                "-x",
-                xgpath.as_ref(),
-                "-p",
-                path_str.as_ref(),
-                "-c",
-                format!("{}", steps).as_ref(),
-            ])
-            .stdout(Stdio::piped())
-            .spawn()?;
-        //.expect("failed to spawn a process");
-        let mut command2 = Command::new(&commands[0])
-            .args(&commands[1..])
-            .args(&["view", "-j", "-"])
-            .stdin(unsafe { Stdio::from_raw_fd(command1.stdout.as_ref().unwrap().as_raw_fd()) })
-            .stdout(Stdio::piped())
-            .spawn()?;
-        //.expect("failed to spawn a process");
-        let mut command3 = Command::new("ruby")
-            .args(&[
-                "proto/graph-helper2.rb",
-                &command,
-                xgpath.as_ref(),
-                &path.path,
-                chr_prefix.as_ref(),
-            ])
-            .stdin(unsafe { Stdio::from_raw_fd(command2.stdout.as_ref().unwrap().as_raw_fd()) })
-            .stdout(out)
-            .spawn()?;
-        //.expect("failed to spawn a process");
-        let status1 = command1.wait()?; //.expect("failed to wait on child");
-        let status2 = command2.wait()?; //.expect("failed to wait on child");
-        let status3 = command3.wait()?; //.expect("failed to wait on child");
-        debug!("Status codes: {} {} {}", status1, status2, status3);
+        let mut pipeline =
+            Pipeline::with_slots(config.bin.find_timeout(), 3, config.bin.max_processes())?;
+        pipeline.spawn(
+            "vg find",
+            Command::new(&commands[0])
+                .args(&commands[1..])
+                .args(&[
+                    "find",
+                    "-x",
+                    xgpath.as_ref(),
+                    "-p",
+                    path_str.as_ref(),
+                    "-c",
+                    format!("{}", steps).as_ref(),
+                ])
+                .stdout(Stdio::piped()),
+        )?;
+        let stdout1 = pipeline.take_stdout()?;
+        pipeline.spawn(
+            "vg view",
+            Command::new(&commands[0])
+                .args(&commands[1..])
+                .args(&["view", "-j", "-"])
+                .stdin(Stdio::from(stdout1))
+                .stdout(Stdio::piped()),
+        )?;
+        let stdout2 = pipeline.take_stdout()?;
+        pipeline.spawn_group(
+            "graph-helper2.rb",
+            group_command("ruby")
+                .args(&[
+                    "proto/graph-helper2.rb",
+                    &command,
+                    xgpath.as_ref(),
+                    &path.path,
+                    chr_prefix.as_ref(),
+                ])
+                .stdin(Stdio::from(stdout2))
+                .stdout(out),
+        )?;
+        pipeline.wait()?;
+        return Ok(true);
+    }
 
-        if status1.success() && status2.success() && status3.success() {
-            return Ok(true);
-        } else {
-            return Ok(false);
-        }
+    fn generate_summary(
+        &self,
+        path: &OptionalRegion,
+        steps: &Option<i64>,
+        config: &Config,
+        data: &ConfigData,
+        database: &Database,
+        max_interval: u64,
+        resolution: u64,
+    ) -> Result<Summary, Error> {
+        check_interval(path, max_interval, true)?;
+        let steps = steps.unwrap_or(DEFAULT_STEP).min(MAX_STEP);
+        let xgpath = VG::replace_file_name(path, &data.source.xg);
+        let path_str = format!("{}", path);
+        let commands: Vec<&str> = config.bin.vg.split(" ").collect();
+        let query = ["-p", path_str.as_ref(), "-c", &steps.to_string()];
+        let mut graph = self.find_subgraph(config, &commands, &xgpath, &query)?;
+        let node_ids: Vec<u64> = graph.node.iter().map(|t| t.id).collect();
+        let regions = node_ids_to_regions(data, database, &node_ids);
+        set_coordinates(&mut graph, &regions, &data.chr_prefix);
+        Ok(summarize(&graph, &path.path, resolution))
     }
 
+    fn node_sequence(
+        &self,
+        config: &Config,
+        data: &ConfigData,
+        database: &Database,
+        node_id: u64,
+    ) -> Result<Option<String>, Error> {
+        let xgpath = match node_graph_file(&data.source.xg, data, database, node_id) {
+            Some(xgpath) => xgpath,
+            None => return Ok(None),
+        };
+        let commands: Vec<&str> = config.bin.vg.split(" ").collect();
+        let query = ["-n", &node_id.to_string(), "-c", "0"];
+        Ok(self
+            .find_subgraph(config, &commands, &xgpath, &query)?
+            .node
+            .into_iter()
+            .find(|t| t.id == node_id)
+            .map(|t| t.sequence))
+    }
+    fn path_names(&self, config: &Config, data: &ConfigData) -> Result<Vec<String>, Error> {
+        // Per-chromosome xgs cannot be listed before knowing the chromosomes.
+        if data.source.xg.contains("{}") {
+            return Ok(vec![]);
+        }
+        let commands: Vec<&str> = config.bin.vg.split(" ").collect();
+        let mut pipeline =
+            Pipeline::with_slots(config.bin.paths_timeout(), 1, config.bin.max_processes())?;
+        pipeline.spawn(
+            "vg paths",
+            Command::new(commands[0])
+                .args(&commands[1..])
+                .args(&["paths", "-L", "-x", &data.source.xg])
+                .stdout(Stdio::piped()),
+        )?;
+        pipeline.capture_stdout();
+        Ok(String::from_utf8_lossy(&pipeline.wait()?)
+            .lines()
+            .map(|t| t.to_string())
+            .collect())
+    }
     fn generate_graph_to_file(
         &self,
         path: OptionalRegion,
@@ -507,69 +790,46 @@ impl Graph for VG {
         file: &File,
         steps: &Option<i64>,
         config: &Config,
+        data: &ConfigData,
+        database: &Database,
         xgfile: &String,
         tmp: bool,
-        max_interval: &String,
+        max_interval: u64,
+        downsample: bool,
     ) -> Result<bool, Error> {
-        let out = unsafe { Stdio::from_raw_fd(file.as_raw_fd()) };
-        let mut steps = steps.unwrap_or(2);
+        let mut steps = steps.unwrap_or(DEFAULT_STEP);
         if steps > MAX_STEP {
             steps = MAX_STEP;
         }
         let xgpath = VG::replace_file_name(&path, xgfile);
-        if path.interval() > Some(max_interval.parse::<u64>().unwrap_or(MAX_INTERVAL)) {
-            return Ok(false);
+        let downsampled = check_interval(&path, max_interval, downsample)?;
+        if downsampled {
+            steps = 0;
         }
+        let path_name = path.path.clone();
         let path = format!("{}", path);
-        let chr_prefix = &config.data[0].chr_prefix;
+        let chr_prefix = &data.chr_prefix;
         debug!("{}, {}", xgpath, path);
         let commands: Vec<&str> = match tmp {
             false => config.bin.vg.split(" ").collect(),
             true => config.bin.vg_tmp.split(" ").collect(),
         };
-        let mut command1 = Command::new(&commands[0])
-            .args(&commands[1..])
-            .args(&[
-                "find",
-                "-x",
-                xgpath.as_ref(),
-                "-p",
-                path.as_ref(),
-                "-c",
-                format!("{}", steps).as_ref(),
-            ])
-            .stdout(Stdio::piped())
-            .spawn()?;
-        //.expect("failed to spawn a process");
-        let mut command2 = Command::new(&commands[0])
-            .args(&commands[1..])
-            .args(&["view", "-j", "-"])
-            .stdin(unsafe { Stdio::from_raw_fd(command1.stdout.as_ref().unwrap().as_raw_fd()) })
-            .stdout(Stdio::piped())
-            .spawn()?;
-        //.expect("failed to spawn a process");
-        let mut command3 = Command::new("ruby")
-            .args(&[
-                "proto/graph-helper.rb",
-                &config.bin.vg,
-                xgpath.as_ref(),
-                chr_prefix.as_ref(),
-            ])
-            .stdin(unsafe { Stdio::from_raw_fd(command2.stdout.as_ref().unwrap().as_raw_fd()) })
-            .stdout(out)
-            .spawn()?;
-        //.expect("failed to spawn a process");
-        //.output().unwrap_or_else(|e| {
-        //    panic!("failed to execute process: {}", e)
-        let status1 = command1.wait()?; //.expect("failed to wait on child");
-        let status2 = command2.wait()?; //.expect("failed to wait on child");
-        let status3 = command3.wait()?; //.expect("failed to wait on child");
-        debug!("Status codes: {} {} {}", status1, status2, status3);
-
-        if status1.success() && status2.success() && status3.success() {
-            return Ok(true);
-        } else {
-            return Ok(false);
+        let query = ["-p", path.as_ref(), "-c", &steps.to_string()];
+        let mut graph = self.find_subgraph(config, &commands, &xgpath, &query)?;
+        if downsampled {
+            graph = downsample_graph(&graph, &path_name, DOWNSAMPLE_NODES);
         }
+        // Uploaded graphs are not in the node index.
+        let regions = match tmp {
+            false => {
+                let node_ids: Vec<u64> = graph.node.iter().map(|t| t.id).collect();
+                node_ids_to_regions(data, database, &node_ids)
+            }
+            true => {
+                self.set_path_coordinates(config, &commands, &xgpath, &mut graph)?;
+                HashMap::new()
+            }
+        };
+        write_sankey(file, &graph, chr_prefix, &regions)
     }
 }